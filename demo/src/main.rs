@@ -3,7 +3,7 @@
 
 use std::{thread::sleep, time::Duration};
 
-use hot_crate::{HotCrate, Utf8PathBuf};
+use hot_crate::{HotCrate, ReloadOutcome, Utf8PathBuf};
 
 use plugin_api::Plugin;
 
@@ -20,8 +20,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     loop {
         sleep(Duration::from_secs(1));
 
-        if plugin.try_reload().unwrap() {
-            println!("RELOADED!");
+        if let ReloadOutcome::Reloaded { duration, .. } = plugin.try_reload()? {
+            println!("RELOADED! ({:?})", duration);
             print_current_plugin(&mut plugin);
         }
     }
@@ -0,0 +1,149 @@
+/*!
+Attribute macros for [`hot_crate`](https://docs.rs/hot_crate)
+*/
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, FnArg, ForeignItemFn, Ident};
+
+/// Generates a type alias and a safe wrapper of [`HotCrate::get`] from a bodyless function
+/// signature
+///
+/// ```no_run
+/// use hot_crate_macros::hot_fn;
+///
+/// #[hot_fn]
+/// pub extern "C" fn add(a: i32, b: i32) -> i32;
+///
+/// // generated:
+/// // pub type AddFn = extern "C" fn(i32, i32) -> i32;
+/// // pub fn add(hot: &hot_crate::HotCrate, a: i32, b: i32) -> hot_crate::Result<i32>;
+///
+/// # fn main() -> hot_crate::Result<()> {
+/// let hot = hot_crate::HotCrate::load_path("/path/to/libplugin.so")?;
+/// assert_eq!(add(&hot, 1, 2)?, 3);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The symbol is looked up by the function name on every call. The wrapper is safe to call only
+/// because the signature is trusted to match the one exported by the dylib. The wrapper of an
+/// `unsafe fn` is `unsafe` too.
+///
+/// [`HotCrate::get`]: https://docs.rs/hot_crate/latest/hot_crate/struct.HotCrate.html#method.get
+#[proc_macro_attribute]
+pub fn hot_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`#[hot_fn]` takes no arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let item = parse_macro_input!(item as ForeignItemFn);
+    self::expand(item)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+fn expand(item: ForeignItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let ForeignItemFn {
+        attrs, vis, sig, ..
+    } = item;
+
+    if sig.abi.is_none() {
+        return Err(syn::Error::new_spanned(
+            sig.fn_token,
+            "`#[hot_fn]` requires an ABI such as `extern \"C\"`",
+        ));
+    }
+
+    if !sig.generics.params.is_empty() || sig.variadic.is_some() {
+        return Err(syn::Error::new_spanned(
+            &sig,
+            "`#[hot_fn]` does not support generics or variadic arguments",
+        ));
+    }
+
+    let name = &sig.ident;
+    let symbol = syn::LitByteStr::new(name.to_string().as_bytes(), name.span());
+    let alias = format_ident!("{}Fn", self::to_camel_case(name));
+
+    let abi = &sig.abi;
+    let unsafety = &sig.unsafety;
+    let output = &sig.output;
+
+    let mut arg_names = Vec::new();
+    let mut arg_types = Vec::new();
+    for (i, input) in sig.inputs.iter().enumerate() {
+        match input {
+            FnArg::Typed(arg) => {
+                arg_names.push(format_ident!("__arg{}", i));
+                arg_types.push(&arg.ty);
+            }
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "`#[hot_fn]` functions can't take `self`",
+                ));
+            }
+        }
+    }
+
+    let ret = match output {
+        syn::ReturnType::Default => quote!(()),
+        syn::ReturnType::Type(_, ty) => quote!(#ty),
+    };
+
+    let doc_alias = format!("Type of the `{}` symbol", name);
+    let doc_wrapper = format!("Calls `{}` loaded from the dylib", name);
+    let doc_safety = unsafety.map(|_| {
+        let doc = format!(
+            " The safety contract of `{}` in the dylib must be upheld.",
+            name
+        );
+        quote! {
+            #[doc = ""]
+            #[doc = " # Safety"]
+            #[doc = ""]
+            #[doc = #doc]
+        }
+    });
+
+    Ok(quote! {
+        #[doc = #doc_alias]
+        #vis type #alias = #unsafety #abi fn(#(#arg_types),*) #output;
+
+        #(#attrs)*
+        #[doc = #doc_wrapper]
+        #doc_safety
+        #vis #unsafety fn #name(
+            hot: &::hot_crate::HotCrate,
+            #(#arg_names: #arg_types),*
+        ) -> ::hot_crate::Result<#ret> {
+            // the signature is declared by the user of `#[hot_fn]`
+            unsafe {
+                let f = hot.get::<#alias>(#symbol)?;
+                Ok(f(#(#arg_names),*))
+            }
+        }
+    })
+}
+
+/// `load_plugin` -> `LoadPlugin`
+fn to_camel_case(ident: &Ident) -> String {
+    ident
+        .to_string()
+        .split('_')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let mut chars = s.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
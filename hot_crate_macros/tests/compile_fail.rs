@@ -0,0 +1,7 @@
+//! Checks the code `#[hot_fn]` must reject
+
+#[test]
+fn compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
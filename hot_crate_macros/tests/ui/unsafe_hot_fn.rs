@@ -0,0 +1,9 @@
+use hot_crate_macros::hot_fn;
+
+#[hot_fn]
+pub unsafe extern "C" fn read_ptr(p: *const u8) -> u8;
+
+fn main() {
+    let hot = hot_crate::HotCrate::load_path("/path/to/libplugin.so").unwrap();
+    let _ = read_ptr(&hot, std::ptr::null());
+}
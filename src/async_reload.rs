@@ -0,0 +1,34 @@
+//! `async` reloading with `tokio` (`tokio` feature)
+
+use crate::{BuildOptions, HotCrate, Result};
+
+impl HotCrate {
+    /// [`HotCrate::try_reload`] that checks the modification time with `tokio::fs`
+    ///
+    /// Copying and opening the library are still blocking.
+    pub async fn try_reload_async(&mut self) -> Result<bool> {
+        let timestamp = tokio::fs::metadata(&self.lib_path).await?.modified().ok();
+
+        if !self.should_reload(timestamp)? {
+            return Ok(false);
+        }
+
+        self.force_reload()?;
+        Ok(true)
+    }
+
+    /// [`HotCrate::try_rebuild_and_reload`] that runs `cargo build` with `tokio::process`
+    pub async fn rebuild_and_reload_async(&mut self) -> Result<bool> {
+        let options = self.build_options.clone();
+        self.rebuild_and_reload_async_with(&options).await
+    }
+
+    /// [`HotCrate::try_rebuild_and_reload_with`] that runs `cargo build` with `tokio::process`
+    pub async fn rebuild_and_reload_async_with(&mut self, options: &BuildOptions) -> Result<bool> {
+        let cmd = self.cargo_build_command(options)?;
+        let output = tokio::process::Command::from(cmd).output().await?;
+        crate::rebuild::check_build_output(&output)?;
+
+        self.reload_built()
+    }
+}
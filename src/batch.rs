@@ -0,0 +1,108 @@
+//! Reloading multiple dylibs all-or-nothing
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use libloading::Library;
+
+use crate::{stats::ReloadStats, HotCrate, Result, Utf8PathBuf};
+
+/// The loaded library and the bookkeeping replaced by [`HotCrate::force_reload`]
+struct Loaded {
+    lib: Arc<Library>,
+    loaded_path: Utf8PathBuf,
+    lib_timestamp: Option<SystemTime>,
+    #[cfg(feature = "checksum")]
+    lib_hash: Option<[u8; 32]>,
+    lib_content_hash: Option<u64>,
+    reload_count: usize,
+    last_reloaded_at: Option<SystemTime>,
+    total_reload_duration: Duration,
+    timed_reloads: u32,
+    reload_stats: ReloadStats,
+}
+
+impl HotCrate {
+    fn loaded(&self) -> Loaded {
+        Loaded {
+            lib: Arc::clone(&self.lib),
+            loaded_path: self.loaded_path.clone(),
+            lib_timestamp: self.lib_timestamp,
+            #[cfg(feature = "checksum")]
+            lib_hash: self.lib_hash,
+            lib_content_hash: self.lib_content_hash,
+            reload_count: self.reload_count,
+            last_reloaded_at: self.last_reloaded_at,
+            total_reload_duration: self.total_reload_duration,
+            timed_reloads: self.timed_reloads,
+            reload_stats: self.reload_stats.clone(),
+        }
+    }
+
+    /// Swaps the previous library back in like a reload, running the reload hooks, and forgets
+    /// the records of the reload that is undone
+    fn restore_loaded(&mut self, loaded: Loaded) {
+        self.hooks.run_before();
+
+        self.symbol_cache
+            .get_mut()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+
+        self.lib = loaded.lib;
+        self.loaded_path = loaded.loaded_path;
+        self.lib_timestamp = loaded.lib_timestamp;
+        #[cfg(feature = "checksum")]
+        {
+            self.lib_hash = loaded.lib_hash;
+        }
+        self.lib_content_hash = loaded.lib_content_hash;
+        self.reload_count = loaded.reload_count;
+        self.last_reloaded_at = loaded.last_reloaded_at;
+        self.total_reload_duration = loaded.total_reload_duration;
+        self.timed_reloads = loaded.timed_reloads;
+        self.reload_stats = loaded.reload_stats;
+
+        self.hooks.run_after();
+    }
+
+    /// Calls [`HotCrate::try_reload`] on each dylib in order. If any of them fails, the
+    /// successfully reloaded ones are rolled back to their previous libraries and the error is
+    /// returned.
+    ///
+    /// Use it when dylibs depend on each other (e.g. plugin B on plugin A) and must be reloaded
+    /// together. Rolled back dylibs are still outdated, so they're reloaded on the next call.
+    /// Rolling back runs the [`HotCrate::on_before_reload`] and [`HotCrate::on_after_reload`]
+    /// hooks again and drops the undone reloads from [`HotCrate::reload_stats`]. Plugin state
+    /// transferred to the new libraries (see [`StateTransfer`]) is not transferred back; the
+    /// previous libraries keep the state they had.
+    ///
+    /// [`StateTransfer`]: crate::StateTransfer
+    pub fn try_reload_batch(crates: &mut [HotCrate]) -> Result<Vec<bool>> {
+        let mut reloaded = Vec::with_capacity(crates.len());
+        let mut previous = Vec::with_capacity(crates.len());
+
+        for i in 0..crates.len() {
+            let loaded = crates[i].loaded();
+            match crates[i].try_reload().map(|outcome| outcome.was_reloaded()) {
+                Ok(b) => {
+                    reloaded.push(b);
+                    previous.push(loaded);
+                }
+                Err(err) => {
+                    for (j, loaded) in previous.into_iter().enumerate() {
+                        if reloaded[j] {
+                            debug_event!("rolling back `{}`", crates[j].package_name);
+                            crates[j].restore_loaded(loaded);
+                        }
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(reloaded)
+    }
+}
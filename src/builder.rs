@@ -0,0 +1,492 @@
+//! Builder of [`HotCrate`]
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use cargo_metadata::{Metadata, MetadataCommand};
+
+use crate::{
+    cleanup,
+    hooks::{CopyHook, ReloadHooks},
+    strategy::Placement,
+    BuildOptions, ChangeDetection, HotCrate, HotCrateError, NetworkAccess, ReloadStrategy, Result,
+    Utf8Path, Utf8PathBuf,
+};
+
+/// Configures [`HotCrate`] before loading it
+///
+/// ```no_run
+/// # fn main() -> hot_crate::Result<()> {
+/// let plugin = hot_crate::HotCrate::builder()
+///     .main_toml("/path/to/main/Cargo.toml")
+///     .dylib_toml("/path/to/plugin/Cargo.toml")
+///     .profile("release")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HotCrateBuilder {
+    main_toml: Option<PathBuf>,
+    dylib_toml: Option<PathBuf>,
+    temp_dir: Option<PathBuf>,
+    reload_count: usize,
+    profile: Option<String>,
+    debounce: Duration,
+    metadata: Option<Metadata>,
+    dylib_path: Option<PathBuf>,
+    build_options: BuildOptions,
+    /// Overrides `build_options.network` regardless of the call order
+    network: Option<NetworkAccess>,
+    target_name: Option<String>,
+    change_detection: ChangeDetection,
+    lock_file: Option<Utf8PathBuf>,
+    wait_for_first_build: Option<Duration>,
+    configure_metadata: Option<ConfigureMetadata>,
+    reload_strategy: Option<ReloadStrategy>,
+    /// `None`: default, `Some(None)`: skipped
+    install_name_tool: Option<Option<PathBuf>>,
+    target_triple: Option<String>,
+    max_reload_copies: Option<usize>,
+    before_copy: Option<CopyHook>,
+    reload_stats_capacity: Option<usize>,
+    load_env: HashMap<String, String>,
+}
+
+/// Closure given to [`HotCrateBuilder::configure_metadata`]
+#[derive(Clone)]
+pub(crate) struct ConfigureMetadata(Arc<dyn Fn(&mut MetadataCommand) + Send + Sync>);
+
+impl fmt::Debug for ConfigureMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ConfigureMetadata(..)")
+    }
+}
+
+impl ConfigureMetadata {
+    /// `cargo metadata` command for the manifest with the user configuration applied
+    pub fn command(this: Option<&Self>, manifest_path: &Utf8Path) -> MetadataCommand {
+        let mut cmd = MetadataCommand::new();
+        cmd.manifest_path(manifest_path);
+        if let Some(this) = this {
+            (this.0)(&mut cmd);
+        }
+        cmd
+    }
+}
+
+impl HotCrateBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// (Required unless [`HotCrateBuilder::metadata`] is given) Absolute path to the `Cargo.toml`
+    /// of the main crate
+    pub fn main_toml(mut self, path: impl AsRef<Path>) -> Self {
+        self.main_toml = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// (Required) Absolute path to the `Cargo.toml` of the `dylib` crate
+    pub fn dylib_toml(mut self, path: impl AsRef<Path>) -> Self {
+        self.dylib_toml = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Directory to put temporary copies of the dylib. Defaults to `${TMP_DIR}/hot_crate`.
+    ///
+    /// With a custom directory, the dylib is hard-linked instead of copied by default if possible
+    /// (except on macOS, where the copy is modified with `install_name_tool`). Put it on the same
+    /// filesystem as the target directory to avoid copying. See also
+    /// [`HotCrateBuilder::reload_strategy`].
+    pub fn temp_dir(mut self, path: impl AsRef<Path>) -> Self {
+        self.temp_dir = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Initial value of [`HotCrate::reload_count`]. Defaults to `0`.
+    pub fn reload_count(mut self, seed: usize) -> Self {
+        self.reload_count = seed;
+        self
+    }
+
+    /// Cargo profile the dylib is built with, such as `"dev"`, `"release"`, `"bench"` or a custom
+    /// profile name. Defaults to the profile of the host, inferred from `debug_assertions`.
+    ///
+    /// Built-in profiles are mapped to their output directories (`dev` and `test` to `debug`,
+    /// `bench` to `release`), and custom profiles are looked up in the directory of the same
+    /// name.
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// Uses already-fetched metadata of the main crate instead of running `cargo metadata`.
+    /// [`HotCrateBuilder::main_toml`] is not needed then.
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Target triple the dylib is cross-compiled for, such as `"x86_64-unknown-linux-musl"`.
+    /// Defaults to `CARGO_BUILD_TARGET` if it's set.
+    ///
+    /// Cargo puts the artifacts in `target/<triple>/<profile>` then. It's also passed to
+    /// `cargo build` on [`HotCrate::try_rebuild_and_reload`].
+    pub fn target_triple(mut self, triple: impl Into<String>) -> Self {
+        self.target_triple = Some(triple.into());
+        self
+    }
+
+    /// Makes [`HotCrate::try_reload`] skip dylibs modified less than `duration` ago. Defaults to
+    /// zero.
+    ///
+    /// `cargo build` can update the dylib file several times, so reloading it right after the
+    /// first modification might load a half-written library.
+    pub fn debounce(mut self, duration: Duration) -> Self {
+        self.debounce = duration;
+        self
+    }
+
+    /// (Alternative to the manifests) Loads a prebuilt dylib file without cargo metadata. See
+    /// [`HotCrate::load_path`].
+    pub fn dylib_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.dylib_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// How [`HotCrate::try_reload`] detects changes of the dylib. Defaults to
+    /// [`ChangeDetection::Mtime`].
+    pub fn change_detection(mut self, change_detection: ChangeDetection) -> Self {
+        self.change_detection = change_detection;
+        self
+    }
+
+    /// Makes [`HotCrate::force_reload`] append a JSON line to the file, recording the time, the
+    /// target name, the SHA-256 (`null` without the `checksum` feature) and the path of the
+    /// loaded copy. Useful for finding which dylib was loaded on a crash.
+    pub fn enable_lock_file(mut self, path: impl AsRef<Utf8Path>) -> Self {
+        self.lock_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Makes [`HotCrateBuilder::build`] wait until the dylib file appears, polling with
+    /// exponential backoff up to `timeout`. Useful when `cargo watch -x build` runs concurrently.
+    /// Fails with [`HotCrateError::Timeout`] if the file never appears.
+    pub fn wait_for_first_build(mut self, timeout: Duration) -> Self {
+        self.wait_for_first_build = Some(timeout);
+        self
+    }
+
+    /// Customizes `cargo metadata` run on build and on [`HotCrate::refresh_metadata`], e.g. with
+    /// `--offline` or `--features`
+    pub fn configure_metadata(
+        mut self,
+        f: impl Fn(&mut MetadataCommand) + Send + Sync + 'static,
+    ) -> Self {
+        self.configure_metadata = Some(ConfigureMetadata(Arc::new(f)));
+        self
+    }
+
+    /// Keeps at most `n` temporary copies made by reloading, removing the oldest ones first.
+    /// Defaults to `usize::MAX` (keep all).
+    ///
+    /// 5 to 10 is recommended for long-running sessions, where the temporary directory grows on
+    /// every reload. Copies still in use (e.g. by [`SharedSymbol`]s on Windows) can't be removed
+    /// and are left.
+    ///
+    /// [`SharedSymbol`]: crate::SharedSymbol
+    pub fn max_reload_copies(mut self, n: usize) -> Self {
+        self.max_reload_copies = Some(n);
+        self
+    }
+
+    /// How the dylib is placed at the temporary path. Defaults to [`ReloadStrategy::HardLink`] if
+    /// [`HotCrateBuilder::temp_dir`] is given (except on macOS and Windows),
+    /// [`ReloadStrategy::Copy`] otherwise.
+    pub fn reload_strategy(mut self, strategy: ReloadStrategy) -> Self {
+        self.reload_strategy = Some(strategy);
+        self
+    }
+
+    /// Path of `install_name_tool` run on reload on macOS, such as
+    /// `/usr/bin/install_name_tool`. Defaults to the one on `PATH`.
+    pub fn install_name_tool_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.install_name_tool = Some(Some(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Skips running `install_name_tool` (and `codesign`) on reload on macOS. Use it only if the
+    /// dylib has no install name issues.
+    pub fn skip_install_name_tool(mut self) -> Self {
+        self.install_name_tool = Some(None);
+        self
+    }
+
+    /// Replaces placing the build artifact (the first argument) at the temporary path (the second
+    /// argument) on reload, such as to strip debug symbols to reduce the copy size. Defaults to
+    /// copying or linking with the [`HotCrateBuilder::reload_strategy`].
+    ///
+    /// The hook must create the file at the temporary path.
+    pub fn before_copy(
+        mut self,
+        hook: impl Fn(&Utf8Path, &Utf8Path) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.before_copy = Some(CopyHook(Arc::new(hook)));
+        self
+    }
+
+    /// Number of recent reloads kept in [`HotCrate::reload_stats`]. Defaults to 32.
+    pub fn reload_stats_capacity(mut self, n: usize) -> Self {
+        self.reload_stats_capacity = Some(n);
+        self
+    }
+
+    /// Environment variables set while opening the dylib, on the initial load and on every reload.
+    /// See [`HotCrate::load_with_env`].
+    pub fn load_env(mut self, env: HashMap<String, String>) -> Self {
+        self.load_env = env;
+        self
+    }
+
+    /// Options of `cargo build` run by [`HotCrate::try_rebuild_and_reload`]
+    pub fn build_options(mut self, options: BuildOptions) -> Self {
+        self.build_options = options;
+        self
+    }
+
+    /// Network access of `cargo build` run by [`HotCrate::try_rebuild_and_reload`]. Defaults to
+    /// [`NetworkAccess::Online`].
+    ///
+    /// [`NetworkAccess::Offline`] is appropriate for dev environments with no internet access or
+    /// hermetic CI builds. Takes precedence over the one in [`HotCrateBuilder::build_options`].
+    pub fn network(mut self, network: NetworkAccess) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Selects the target of the `dylib` package. Defaults to the first `dylib` or `cdylib`
+    /// target.
+    pub fn target_name(mut self, name: impl Into<String>) -> Self {
+        self.target_name = Some(name.into());
+        self
+    }
+
+    /// Loads the `dylib` crate
+    ///
+    /// See [`Library::new`](libloading::Library::new) for thread safety.
+    pub fn build(self) -> Result<HotCrate> {
+        let temp_dir = match self.temp_dir {
+            Some(ref dir) => dir.clone(),
+            None => std::env::temp_dir().join("hot_crate"),
+        };
+        let temp_dir =
+            Utf8PathBuf::from_path_buf(temp_dir).map_err(HotCrateError::Utf8PathConversion)?;
+
+        let target_triple = crate::target_triple(self.target_triple);
+
+        let (
+            main_metadata,
+            main_toml,
+            dylib_toml,
+            pkg_manifest,
+            package_name,
+            target_name,
+            lib_path,
+        ) = match self.dylib_path {
+            Some(ref path) => {
+                let path = Utf8PathBuf::from_path_buf(path.clone())
+                    .map_err(HotCrateError::Utf8PathConversion)?;
+                let name = self::lib_name_from_path(&path);
+                (None, None, None, None, name.clone(), name, path)
+            }
+            None => {
+                let dylib_toml = self::manifest_path(self.dylib_toml, "dylib_toml")?;
+
+                let (main_metadata, main_toml) = match self.metadata {
+                    Some(metadata) => {
+                        let main_toml = metadata.workspace_root.join("Cargo.toml");
+                        (metadata, main_toml)
+                    }
+                    None => {
+                        let main_toml = self::manifest_path(self.main_toml, "main_toml")?;
+                        let metadata = ConfigureMetadata::command(
+                            self.configure_metadata.as_ref(),
+                            &main_toml,
+                        )
+                        .exec()?;
+                        (metadata, main_toml)
+                    }
+                };
+
+                let pkg = crate::find_dylib_pkg(&main_metadata, &dylib_toml)?;
+                let target = crate::find_dylib_target(
+                    &main_metadata,
+                    &dylib_toml,
+                    self.target_name.as_deref(),
+                )?;
+                let pkg_manifest = pkg.manifest_path.clone();
+                let (package_name, target_name) = (pkg.name.clone(), target.name.clone());
+
+                let lib_path = crate::find_dylib_path(
+                    &main_metadata,
+                    target,
+                    self.profile.as_deref(),
+                    target_triple.as_deref(),
+                )?;
+
+                (
+                    Some(main_metadata),
+                    Some(main_toml),
+                    Some(dylib_toml),
+                    Some(pkg_manifest),
+                    package_name,
+                    target_name,
+                    lib_path,
+                )
+            }
+        };
+
+        if let Some(timeout) = self.wait_for_first_build {
+            self::wait_for_file(&lib_path, timeout)?;
+        }
+
+        let install_name_tool = self
+            .install_name_tool
+            .unwrap_or_else(|| Some(PathBuf::from("install_name_tool")));
+        let reload_strategy = self.reload_strategy.unwrap_or(
+            if self.temp_dir.is_some() && !cfg!(any(target_os = "macos", target_os = "windows")) {
+                ReloadStrategy::HardLink
+            } else {
+                ReloadStrategy::Copy
+            },
+        );
+
+        // load a temporary copy like on reload, so that the build artifact is not locked on Windows
+        let mut temp_copies = cleanup::TempCopies::new(
+            &temp_dir,
+            &package_name,
+            &target_name,
+            self.max_reload_copies.unwrap_or(usize::MAX),
+        );
+        let loaded_path = Placement {
+            temp_dir: &temp_dir,
+            package_name: &package_name,
+            target_name: &target_name,
+            strategy: reload_strategy,
+            copy_hook: self.before_copy.as_ref(),
+            install_name_tool: install_name_tool.as_deref(),
+        }
+        .place(&lib_path)?;
+        temp_copies.push(loaded_path.clone());
+
+        let lib = Arc::new(unsafe {
+            crate::deps::open_library(&loaded_path, &lib_path, &self.load_env)?
+        });
+        let lib_timestamp = fs::metadata(&lib_path)?.modified().ok();
+        let lib_content_hash = match lib_timestamp {
+            Some(_) => None,
+            None => {
+                log::warn!(
+                    "modification time is not supported for `{}`; comparing hashes of the file instead",
+                    lib_path
+                );
+                Some(crate::change::content_hash(&lib_path)?)
+            }
+        };
+        #[cfg(feature = "checksum")]
+        let lib_hash = match self.change_detection {
+            ChangeDetection::Sha256Hash => Some(crate::change::sha256_file(&lib_path)?),
+            ChangeDetection::Mtime => None,
+        };
+
+        let mut build_options = self.build_options;
+        if let Some(network) = self.network {
+            build_options.network = network;
+        }
+
+        Ok(HotCrate {
+            main_metadata,
+            main_toml,
+            dylib_toml,
+            pkg_manifest,
+            package_name,
+            target_name,
+            lib,
+            guards: Default::default(),
+            pins: Default::default(),
+            loaded_path,
+            lib_path,
+            temp_copies,
+            lib_timestamp,
+            change_detection: self.change_detection,
+            #[cfg(feature = "checksum")]
+            lib_hash,
+            lib_content_hash,
+            reload_count: self.reload_count,
+            last_reloaded_at: None,
+            total_reload_duration: Duration::ZERO,
+            timed_reloads: 0,
+            reload_stats: crate::stats::ReloadStats::new(self.reload_stats_capacity.unwrap_or(32)),
+            build_noticed_at: None,
+            install_name_tool,
+            reload_strategy,
+            temp_dir,
+            profile: self.profile,
+            target_triple,
+            debounce: self.debounce,
+            build_options,
+            lock_file: self.lock_file,
+            configure_metadata: self.configure_metadata,
+            symbol_cache: Default::default(),
+            load_env: self.load_env,
+            hooks: ReloadHooks {
+                copy: self.before_copy,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// `libfoo.so` -> `foo`
+fn lib_name_from_path(path: &Utf8Path) -> String {
+    let stem = path.file_stem().unwrap_or_default();
+    stem.strip_prefix(crate::DYLIB_PREFIX)
+        .unwrap_or(stem)
+        .to_string()
+}
+
+/// Polls until the file exists
+fn wait_for_file(path: &Utf8Path, timeout: Duration) -> Result<()> {
+    let start = Instant::now();
+    let mut interval = Duration::from_millis(10);
+
+    while !path.is_file() {
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Err(HotCrateError::Timeout(path.to_path_buf()));
+        }
+
+        std::thread::sleep(interval.min(timeout - elapsed));
+        interval = (interval * 2).min(Duration::from_secs(1));
+    }
+
+    Ok(())
+}
+
+/// Validates a manifest path given to the builder
+fn manifest_path(path: Option<PathBuf>, field: &'static str) -> Result<Utf8PathBuf> {
+    let path = path.ok_or(HotCrateError::MissingManifest(field))?;
+    let path = Utf8PathBuf::from_path_buf(path).map_err(HotCrateError::Utf8PathConversion)?;
+
+    if !path.is_file() {
+        return Err(HotCrateError::ManifestNotFound(path));
+    }
+
+    Ok(path)
+}
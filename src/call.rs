@@ -0,0 +1,34 @@
+//! Calling `extern "C"` functions in one step
+
+use crate::{HotCrate, Result};
+
+macro_rules! impl_call {
+    ($($name:ident($($arg:ident: $ty:ident),*);)*) => {
+        impl HotCrate {
+            $(
+                /// Looks up an `extern "C"` function and calls it with the arguments
+                ///
+                /// # Safety
+                ///
+                /// The symbol must be an `extern "C"` function with the argument and return types.
+                #[allow(clippy::too_many_arguments)]
+                pub unsafe fn $name<$($ty,)* R>(&self, symbol: &[u8], $($arg: $ty),*) -> Result<R> {
+                    let f = self.lib.get::<extern "C" fn($($ty),*) -> R>(symbol)?;
+                    Ok(f($($arg),*))
+                }
+            )*
+        }
+    };
+}
+
+impl_call! {
+    call0();
+    call1(a: A);
+    call2(a: A, b: B);
+    call3(a: A, b: B, c: C);
+    call4(a: A, b: B, c: C, d: D);
+    call5(a: A, b: B, c: C, d: D, e: E);
+    call6(a: A, b: B, c: C, d: D, e: E, f: F);
+    call7(a: A, b: B, c: C, d: D, e: E, f: F, g: G);
+    call8(a: A, b: B, c: C, d: D, e: E, f: F, g: G, h: H);
+}
@@ -0,0 +1,33 @@
+//! Detecting changes of the build artifact
+
+/// How [`HotCrate::try_reload`](crate::HotCrate::try_reload) detects that the dylib is updated.
+/// Set with [`HotCrateBuilder::change_detection`](crate::HotCrateBuilder::change_detection).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ChangeDetection {
+    /// Compares modification times of the file
+    #[default]
+    Mtime,
+    /// Compares SHA-256 hashes of the file, which is reliable on network filesystems or when the
+    /// file is touched without changes. Requires the `checksum` feature.
+    #[cfg(feature = "checksum")]
+    Sha256Hash,
+}
+
+/// Non-cryptographic hash of the file contents, used when modification times are not supported
+pub(crate) fn content_hash(path: &crate::Utf8Path) -> crate::Result<u64> {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    std::fs::read(path)?.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+#[cfg(feature = "checksum")]
+pub(crate) fn sha256_file(path: &crate::Utf8Path) -> crate::Result<[u8; 32]> {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
@@ -0,0 +1,310 @@
+//! Removing temporary copies of the dylib
+
+use std::{
+    collections::VecDeque,
+    fs, io,
+    time::{Duration, SystemTime},
+};
+
+use crate::{HotCrate, Result, Utf8Path, Utf8PathBuf, DYLIB_EXTENSION, DYLIB_PREFIX};
+
+/// Removes the temporary copies made by a [`HotCrate`] on drop
+///
+/// Held by [`HotCrate`] after the library so that the library is closed before its file is
+/// removed. Files that can't be removed (e.g. still opened on Windows) are left.
+#[derive(Debug)]
+pub(crate) struct TempCopies {
+    /// `${TMP_DIR}/hot_crate/${package}`
+    pub(crate) dir: Utf8PathBuf,
+    pub(crate) target_name: String,
+    /// [`HotCrateBuilder::max_reload_copies`](crate::HotCrateBuilder::max_reload_copies)
+    pub(crate) max_copies: usize,
+    /// Copies made by this instance, oldest first
+    history: VecDeque<Utf8PathBuf>,
+}
+
+impl TempCopies {
+    pub fn new(
+        temp_dir: &Utf8Path,
+        package_name: &str,
+        target_name: &str,
+        max_copies: usize,
+    ) -> Self {
+        Self {
+            dir: temp_dir.join(package_name),
+            target_name: target_name.to_string(),
+            max_copies,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Records the newly loaded copy and removes the oldest ones beyond `max_copies`
+    ///
+    /// A copy loaded again (such as by [`HotCrate::restore`]) is moved to the newest.
+    pub fn push(&mut self, path: Utf8PathBuf) {
+        self.history.retain(|p| *p != path);
+        self.history.push_back(path);
+
+        // the loaded copy is always kept
+        while self.history.len() > self.max_copies.max(1) {
+            let path = self.history.pop_front().unwrap();
+            if let Err(err) = fs::remove_file(&path) {
+                debug_event!("failed to remove `{}`: {}", path, err);
+            }
+        }
+    }
+
+    /// Whether the file name follows the naming convention of [`HotCrate::tmp_dylib_path`]
+    fn matches(&self, file_name: &str) -> bool {
+        let prefix = format!("{}{}-", DYLIB_PREFIX, self.target_name);
+        let suffix = format!(".{}", DYLIB_EXTENSION);
+
+        file_name.len() > prefix.len() + suffix.len()
+            && file_name.starts_with(&prefix)
+            && file_name.ends_with(&suffix)
+    }
+
+    /// Removes the copies made by this instance except `loaded`
+    fn remove_history(&mut self, loaded: &Utf8Path) {
+        for path in std::mem::take(&mut self.history) {
+            if path == loaded {
+                self.history.push_back(path);
+            } else if let Err(err) = fs::remove_file(&path) {
+                debug_event!("failed to remove `{}`: {}", path, err);
+            }
+        }
+    }
+
+    /// Keeps the `keep` most recently created copies in the directory and removes the rest except
+    /// `in_use`. Returns the number of removed files.
+    fn gc(&self, keep: usize, in_use: &[&Utf8Path]) -> Result<usize> {
+        let mut copies = Vec::new();
+        for path in self.list()? {
+            let metadata = fs::metadata(&path)?;
+            let created = metadata.created().or_else(|_| metadata.modified())?;
+            copies.push((created, path));
+        }
+
+        // newest first
+        copies.sort_by_key(|(created, _)| std::cmp::Reverse(*created));
+
+        let mut n_removed = 0;
+        for (_, path) in copies.into_iter().skip(keep) {
+            if in_use.contains(&path.as_path()) {
+                continue;
+            }
+
+            if self::remove_copy(&path)? {
+                n_removed += 1;
+            }
+        }
+
+        Ok(n_removed)
+    }
+
+    /// Paths of the copies in the directory. Empty if the directory does not exist.
+    fn list(&self) -> io::Result<Vec<Utf8PathBuf>> {
+        let entries = match self.dir.read_dir_utf8() {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut paths = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if self.matches(entry.file_name()) {
+                paths.push(entry.into_path());
+            }
+        }
+
+        Ok(paths)
+    }
+}
+
+/// Removes a copy found in the directory. Returns false if it's already removed or in use (e.g. by
+/// another process on Windows).
+fn remove_copy(path: &Utf8Path) -> io::Result<bool> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(err)
+            if err.kind() == io::ErrorKind::NotFound
+                || err.kind() == io::ErrorKind::PermissionDenied =>
+        {
+            Ok(false)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Removes only the copies made by this instance. Other instances (clones, or other processes
+/// sharing the directory) may still use theirs.
+impl Drop for TempCopies {
+    fn drop(&mut self) {
+        for path in self.history.drain(..) {
+            if let Err(err) = fs::remove_file(&path) {
+                debug_event!("failed to remove `{}`: {}", path, err);
+            }
+        }
+    }
+}
+
+impl HotCrate {
+    /// Resets [`HotCrate::reload_count`] to zero and removes the old temporary copies made by
+    /// this instance, such as when restarting a dev session without restarting the process
+    ///
+    /// The currently loaded copy is kept. Copies that can't be removed are left.
+    pub fn reset_reload_counter(&mut self) {
+        self.reload_count = 0;
+        self.temp_copies.remove_history(&self.loaded_path);
+    }
+
+    /// Removes temporary copies of the dylib modified more than `older_than` ago. Returns the
+    /// number of removed files.
+    ///
+    /// The currently loaded copy and the watched one are kept. Copies that can't be removed
+    /// because they're in use are skipped. Unlike this, dropping the [`HotCrate`] removes only
+    /// the copies made by the instance.
+    pub fn clean_stale_copies(&self, older_than: Duration) -> Result<usize> {
+        let now = SystemTime::now();
+        let mut n_removed = 0;
+
+        for path in self.temp_copies.list()? {
+            // the build artifact can be a copy after `force_reload_from_path`
+            if path == self.loaded_path || path == self.lib_path {
+                continue;
+            }
+
+            let modified = fs::metadata(&path)?.modified()?;
+            if now.duration_since(modified).unwrap_or_default() < older_than {
+                continue;
+            }
+
+            if self::remove_copy(&path)? {
+                n_removed += 1;
+            }
+        }
+
+        Ok(n_removed)
+    }
+
+    /// Keeps the `keep` most recently created temporary copies of the dylib and removes the rest.
+    /// Returns the number of removed files.
+    ///
+    /// Scans the directory like [`HotCrate::clean_stale_copies`], so copies made by other
+    /// instances of the same dylib are included. The currently loaded copy is never removed.
+    /// Modification times are used on platforms without creation times.
+    pub fn gc_temp_files(&self, keep: usize) -> Result<usize> {
+        // the build artifact can be a copy after `force_reload_from_path`
+        self.temp_copies
+            .gc(keep, &[&self.loaded_path, &self.lib_path])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_copies(test: &str) -> TempCopies {
+        let temp_dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!(
+                "hot_crate-test-{}-{}",
+                test,
+                uuid::Uuid::new_v4().simple()
+            ));
+        TempCopies::new(&temp_dir, "plugin", "plugin", 4)
+    }
+
+    fn touch(copies: &TempCopies, name: &str) -> Utf8PathBuf {
+        fs::create_dir_all(&copies.dir).unwrap();
+        let path = copies.dir.join(name);
+        fs::write(&path, b"").unwrap();
+        path
+    }
+
+    fn copy_name(id: &str) -> String {
+        format!("{}plugin-{}.{}", DYLIB_PREFIX, id, DYLIB_EXTENSION)
+    }
+
+    #[test]
+    fn matches_copy_names() {
+        let copies = temp_copies("matches");
+
+        assert!(copies.matches(&copy_name("0123abcd")));
+        assert!(!copies.matches(&copy_name("")));
+        assert!(!copies.matches(&format!("{}plugin.{}", DYLIB_PREFIX, DYLIB_EXTENSION)));
+        assert!(!copies.matches(&format!("{}other-0123.{}", DYLIB_PREFIX, DYLIB_EXTENSION)));
+        assert!(!copies.matches(&format!("{}plugin-0123.txt", DYLIB_PREFIX)));
+    }
+
+    #[test]
+    fn push_keeps_max_copies() {
+        let mut copies = temp_copies("push");
+        copies.max_copies = 2;
+
+        let paths: Vec<_> = (0..3)
+            .map(|i| touch(&copies, &copy_name(&i.to_string())))
+            .collect();
+        for path in &paths {
+            copies.push(path.clone());
+        }
+
+        assert!(!paths[0].exists());
+        assert!(paths[1].exists());
+        assert!(paths[2].exists());
+
+        let dir = copies.dir.clone();
+        drop(copies);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn drop_keeps_copies_of_other_instances() {
+        let mut original = temp_copies("drop");
+        let mut clone = TempCopies::new(
+            original.dir.parent().unwrap(),
+            "plugin",
+            "plugin",
+            original.max_copies,
+        );
+
+        let original_copy = touch(&original, &copy_name("original"));
+        let clone_copy = touch(&original, &copy_name("clone"));
+        original.push(original_copy.clone());
+        clone.push(clone_copy.clone());
+
+        drop(clone);
+        assert!(original_copy.exists());
+        assert!(!clone_copy.exists());
+
+        let dir = original.dir.clone();
+        drop(original);
+        assert!(!original_copy.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn gc_keeps_newest_and_in_use() {
+        let copies = temp_copies("gc");
+
+        let paths: Vec<_> = (0..4)
+            .map(|i| {
+                // distinct creation times
+                std::thread::sleep(Duration::from_millis(20));
+                touch(&copies, &copy_name(&i.to_string()))
+            })
+            .collect();
+        let unrelated = touch(&copies, "unrelated.txt");
+
+        // keeps the 2 newest and the oldest one in use
+        assert_eq!(copies.gc(2, &[&paths[0]]).unwrap(), 1);
+        assert!(paths[0].exists());
+        assert!(!paths[1].exists());
+        assert!(paths[2].exists());
+        assert!(paths[3].exists());
+        assert!(unrelated.exists());
+
+        fs::remove_dir_all(&copies.dir).unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+//! Serializable configuration of [`HotCrate`]
+
+use std::{path::PathBuf, time::Duration};
+
+use crate::{BuildOptions, HotCrate, Result};
+
+/// User-configurable parameters of [`HotCrate`], which can be stored in a file with the `serde`
+/// feature. Loaded with [`HotCrate::load_from_config`].
+///
+/// See [`HotCrateBuilder`](crate::HotCrateBuilder) for the meaning of each field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct HotCrateConfig {
+    pub main_toml: PathBuf,
+    pub dylib_toml: PathBuf,
+    pub profile: Option<String>,
+    pub temp_dir: Option<PathBuf>,
+    pub debounce: Duration,
+    /// `--features` of `cargo build` run by [`HotCrate::try_rebuild_and_reload`]
+    pub features: Vec<String>,
+}
+
+impl HotCrate {
+    /// Loads the `dylib` crate with the configuration
+    pub fn load_from_config(config: &HotCrateConfig) -> Result<Self> {
+        let mut builder = Self::builder()
+            .main_toml(&config.main_toml)
+            .dylib_toml(&config.dylib_toml)
+            .debounce(config.debounce)
+            .build_options(BuildOptions {
+                features: config.features.clone(),
+                ..Default::default()
+            });
+
+        if let Some(ref profile) = config.profile {
+            builder = builder.profile(profile.clone());
+        }
+
+        if let Some(ref temp_dir) = config.temp_dir {
+            builder = builder.temp_dir(temp_dir);
+        }
+
+        builder.build()
+    }
+}
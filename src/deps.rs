@@ -0,0 +1,223 @@
+//! Opening dylibs that depend on other dylibs in the target directory
+
+use std::{collections::HashMap, env, ffi::OsString, fs, sync::Mutex};
+
+use goblin::Object;
+use libloading::Library;
+
+use crate::{Result, Utf8Path, Utf8PathBuf};
+
+/// Searched for the dependencies of a DLL on every load
+#[cfg(target_os = "windows")]
+const LIBRARY_PATH_VAR: &str = "PATH";
+
+/// Serializes modifications of the library search path variable and the load environment
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Opens the dylib at `path`, resolving its dependencies next to the build `artifact`
+///
+/// The dependencies found in the directories of the artifact are opened by absolute paths
+/// beforehand. The loader reuses them (matched by their install names) and keeps them open while
+/// the dylib is open. On Windows, the directories are also added to `PATH` while opening the
+/// dylib, for the dependencies of the dependencies. Other platforms read the search path
+/// variables only at startup, so the process environment is left untouched there.
+///
+/// The variables in `load_env` are set while opening the dylib and restored afterward.
+///
+/// # Safety
+///
+/// See [`Library::new`].
+pub(crate) unsafe fn open_library(
+    path: &Utf8Path,
+    artifact: &Utf8Path,
+    load_env: &HashMap<String, String>,
+) -> Result<Library> {
+    let dirs = self::search_dirs(artifact);
+    let deps = self::local_deps(path, &dirs);
+
+    let sets_env = !load_env.is_empty() || (cfg!(target_os = "windows") && !deps.is_empty());
+    let _lock = sets_env.then(|| ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner()));
+
+    let old_env: Vec<_> = load_env
+        .iter()
+        .map(|(key, value)| {
+            let old = env::var_os(key);
+            env::set_var(key, value);
+            (key, old)
+        })
+        .collect();
+
+    let lib = self::open_with_deps(path, &dirs, &deps);
+
+    for (key, old) in old_env.into_iter().rev() {
+        self::restore_var(key, old);
+    }
+
+    lib
+}
+
+unsafe fn open_with_deps(
+    path: &Utf8Path,
+    #[cfg_attr(not(target_os = "windows"), allow(unused_variables))] dirs: &[Utf8PathBuf],
+    deps: &[Utf8PathBuf],
+) -> Result<Library> {
+    if deps.is_empty() {
+        return self::dlopen(path);
+    }
+
+    #[cfg(target_os = "windows")]
+    let old_var = {
+        let old_var = env::var_os(LIBRARY_PATH_VAR);
+        let mut paths: Vec<_> = dirs
+            .iter()
+            .map(|dir| dir.as_std_path().to_path_buf())
+            .collect();
+        if let Some(ref old_var) = old_var {
+            paths.extend(env::split_paths(old_var));
+        }
+        if let Ok(joined) = env::join_paths(paths) {
+            env::set_var(LIBRARY_PATH_VAR, joined);
+        }
+        old_var
+    };
+
+    let preloaded: Vec<Library> = deps
+        .iter()
+        .filter_map(|dep| match self::dlopen(dep) {
+            Ok(lib) => Some(lib),
+            Err(err) => {
+                debug_event!("failed to preload `{}`: {}", dep, err);
+                None
+            }
+        })
+        .collect();
+    debug_event!("preloaded {} dependencies of `{}`", preloaded.len(), path);
+
+    let lib = self::dlopen(path);
+
+    #[cfg(target_os = "windows")]
+    self::restore_var(LIBRARY_PATH_VAR, old_var);
+    drop(preloaded);
+
+    lib
+}
+
+/// [`Library::new`], except on Solaris and illumos
+///
+/// The runtime linker of Solaris and illumos does not make symbols of libraries opened with
+/// `RTLD_LOCAL` (the `libloading` default) available to those loaded later, which breaks the
+/// preloaded dependencies. So they're opened with `RTLD_GLOBAL` there, until `libloading` takes
+/// the flags in `Library::new`.
+unsafe fn dlopen(path: &Utf8Path) -> Result<Library> {
+    #[cfg(any(target_os = "solaris", target_os = "illumos"))]
+    {
+        use libloading::os::unix;
+        let lib = unix::Library::open(Some(path), unix::RTLD_NOW | unix::RTLD_GLOBAL)?;
+        Ok(lib.into())
+    }
+
+    #[cfg(not(any(target_os = "solaris", target_os = "illumos")))]
+    {
+        Ok(Library::new(path)?)
+    }
+}
+
+/// Whether the dylib at `path` is already opened in the process, such as by a clone or a
+/// [`SharedSymbol`](crate::SharedSymbol) holding an old library. `dlopen` returns the same handle
+/// for it instead of loading the file again.
+#[cfg(target_os = "linux")]
+pub(crate) fn is_open(path: &Utf8Path) -> bool {
+    use libloading::os::unix;
+
+    /// Same in glibc and musl
+    const RTLD_NOLOAD: std::os::raw::c_int = 0x4;
+
+    // SAFETY: `RTLD_NOLOAD` only returns an already opened library, so no initializer runs
+    unsafe { unix::Library::open(Some(path), unix::RTLD_LAZY | RTLD_NOLOAD).is_ok() }
+}
+
+fn restore_var(key: &str, old_var: Option<OsString>) {
+    match old_var {
+        Some(old_var) => env::set_var(key, old_var),
+        None => env::remove_var(key),
+    }
+}
+
+/// `target/<profile>` and `target/<profile>/deps`
+fn search_dirs(artifact: &Utf8Path) -> Vec<Utf8PathBuf> {
+    match artifact.parent() {
+        Some(dir) => vec![dir.to_path_buf(), dir.join("deps")],
+        None => Vec::new(),
+    }
+}
+
+/// Paths of the needed libraries of the dylib that exist in `dirs`
+fn local_deps(path: &Utf8Path, dirs: &[Utf8PathBuf]) -> Vec<Utf8PathBuf> {
+    let needed = match self::needed_libs(path) {
+        Ok(needed) => needed,
+        Err(err) => {
+            debug_event!("failed to read dependencies of `{}`: {}", path, err);
+            return Vec::new();
+        }
+    };
+
+    needed
+        .iter()
+        .filter_map(|name| {
+            // install names on macOS are paths such as `@rpath/libfoo.dylib`
+            let file_name = Utf8Path::new(name).file_name()?;
+            dirs.iter()
+                .map(|dir| dir.join(file_name))
+                .find(|candidate| candidate.is_file())
+        })
+        .collect()
+}
+
+/// `@rpath` install names of the needed libraries of the dylib that exist in the directories of
+/// the build `artifact`, paired with their absolute paths
+///
+/// The `@rpath` of the dylib points to the target directory, relative to its location. So they
+/// are not found from the temporary copy unless changed with `install_name_tool -change`.
+pub(crate) fn rpath_deps(path: &Utf8Path, artifact: &Utf8Path) -> Vec<(String, Utf8PathBuf)> {
+    let needed = match self::needed_libs(path) {
+        Ok(needed) => needed,
+        Err(err) => {
+            debug_event!("failed to read dependencies of `{}`: {}", path, err);
+            return Vec::new();
+        }
+    };
+
+    let dirs = self::search_dirs(artifact);
+    needed
+        .into_iter()
+        .filter_map(|name| {
+            let file_name = name.strip_prefix("@rpath/")?;
+            let dep = dirs
+                .iter()
+                .map(|dir| dir.join(file_name))
+                .find(|candidate| candidate.is_file())?;
+            Some((name, dep))
+        })
+        .collect()
+}
+
+/// Names of the libraries the dylib links to
+///
+/// The file is memory-mapped so that only the pages holding the headers and the dynamic section
+/// are read, instead of the whole dylib on every load.
+fn needed_libs(path: &Utf8Path) -> Result<Vec<String>> {
+    let file = fs::File::open(path)?;
+    // SAFETY: `path` is the temporary copy owned by the `HotCrate` (or a dylib given by the
+    // user), which is not modified while it's mapped
+    let bytes = unsafe { memmap2::Mmap::map(&file)? };
+
+    let libs = match Object::parse(&bytes)? {
+        Object::Elf(elf) => elf.libraries,
+        // the first entry is the library itself
+        Object::Mach(goblin::mach::Mach::Binary(macho)) => macho.libs.into_iter().skip(1).collect(),
+        Object::PE(pe) => pe.libraries,
+        _ => Vec::new(),
+    };
+
+    Ok(libs.into_iter().map(|s| s.to_string()).collect())
+}
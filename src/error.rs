@@ -0,0 +1,204 @@
+//! Error type of `hot_crate`
+
+use std::{fmt, io, path::PathBuf};
+
+use crate::Utf8PathBuf;
+
+/// Result type of `hot_crate`
+pub type Result<T, E = HotCrateError> = std::result::Result<T, E>;
+
+/// Failure modes of [`HotCrate`](crate::HotCrate)
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HotCrateError {
+    /// The manifest does not exist or no package in the cargo metadata has it
+    ManifestNotFound(Utf8PathBuf),
+    /// A required manifest path was not given to the builder
+    MissingManifest(&'static str),
+    /// The package has no `dylib` or `cdylib` target
+    DylibTargetMissing(Utf8PathBuf),
+    LibraryLoadFailed(libloading::Error),
+    MetadataFailed(cargo_metadata::Error),
+    IoError(io::Error),
+    /// A path is not valid UTF-8
+    Utf8PathConversion(PathBuf),
+    /// Reloading is blocked by alive [`SymbolGuard`](crate::SymbolGuard)s
+    Busy,
+    /// Reloading is blocked by an alive [`PinnedHotCrate`](crate::PinnedHotCrate)
+    Pinned,
+    /// The operation requires cargo metadata, but the dylib was loaded from a path
+    NoMetadata,
+    /// `cargo build` failed with the stderr output
+    BuildFailed(String),
+    /// Failed to parse a dylib file
+    ObjectParseFailed(goblin::error::Error),
+    /// The dylib file is not an ELF, a (thin) Mach-O or a PE file
+    UnsupportedObject(Utf8PathBuf),
+    /// `install_name_tool` (or `codesign` on Apple Silicon) failed on the temporary copy on macOS
+    InstallNameToolFailed(String),
+    /// The dylib file did not appear within the timeout of
+    /// [`HotCrateBuilder::wait_for_first_build`](crate::HotCrateBuilder::wait_for_first_build)
+    Timeout(Utf8PathBuf),
+    /// [`HotCrate::try_reload_with_timeout`](crate::HotCrate::try_reload_with_timeout) could not
+    /// load the dylib within the timeout, with the last error
+    ReloadTimeout(Box<HotCrateError>),
+    /// The symbol is not registered to the [`MockHotCrate`](crate::MockHotCrate), or not exported
+    /// by the dylib file or the WASM module
+    SymbolNotFound(String),
+    /// `hot_crate_api_version` of the dylib differs from the one expected by
+    /// [`VersionedHotCrate`](crate::VersionedHotCrate)
+    AbiVersionMismatch {
+        expected: u32,
+        found: u32,
+    },
+    /// The dylib does not export a symbol required by
+    /// [`HotCrate::load_checked`](crate::HotCrate::load_checked)
+    MissingRequiredSymbol(String),
+    /// A key of `[package.metadata.hot_crate]` has an unexpected type
+    InvalidPluginMetadata(String),
+    /// The temporary copy of a [`Snapshot`](crate::Snapshot) is removed
+    SnapshotMissing(Utf8PathBuf),
+    #[cfg(feature = "watch")]
+    WatchFailed(notify::Error),
+    #[cfg(feature = "manifest")]
+    ManifestParseFailed(toml_edit::TomlError),
+    /// Failed to compile, instantiate or call a WASM module
+    #[cfg(feature = "wasm")]
+    WasmFailed(wasmtime::Error),
+}
+
+impl fmt::Display for HotCrateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ManifestNotFound(path) => {
+                write!(f, "no package found with manifest at {}", path)
+            }
+            Self::MissingManifest(field) => {
+                write!(f, "manifest path `{}` is not given to the builder", field)
+            }
+            Self::DylibTargetMissing(path) => write!(
+                f,
+                "no target with crate-type 'dylib' or 'cdylib' found in manifest at {}",
+                path
+            ),
+            Self::LibraryLoadFailed(_) => write!(f, "failed to load the dynamic library"),
+            Self::MetadataFailed(_) => write!(f, "failed to run `cargo metadata`"),
+            Self::IoError(_) => write!(f, "IO error"),
+            Self::Utf8PathConversion(path) => {
+                write!(f, "path is not valid UTF-8: {}", path.display())
+            }
+            Self::Busy => write!(f, "symbol guards are alive and block reloading"),
+            Self::Pinned => write!(f, "the dylib is pinned and can't be reloaded"),
+            Self::NoMetadata => write!(f, "the dylib was loaded without cargo metadata"),
+            Self::BuildFailed(stderr) => write!(f, "`cargo build` failed:\n{}", stderr),
+            Self::ObjectParseFailed(_) => write!(f, "failed to parse the dylib file"),
+            Self::UnsupportedObject(path) => write!(f, "unsupported object file: {}", path),
+            Self::InstallNameToolFailed(msg) => write!(f, "failed to patch the dylib: {}", msg),
+            Self::Timeout(path) => write!(f, "timed out waiting for the dylib at {}", path),
+            Self::ReloadTimeout(_) => write!(f, "timed out reloading the dylib"),
+            Self::SymbolNotFound(name) => write!(f, "symbol not found: {}", name),
+            Self::AbiVersionMismatch { expected, found } => write!(
+                f,
+                "ABI version mismatch: expected {}, found {}",
+                expected, found
+            ),
+            Self::MissingRequiredSymbol(name) => {
+                write!(f, "the dylib does not export required symbol `{}`", name)
+            }
+            Self::InvalidPluginMetadata(key) => {
+                write!(f, "invalid `package.metadata.{}` in the manifest", key)
+            }
+            Self::SnapshotMissing(path) => {
+                write!(f, "the snapshotted dylib is removed: {}", path)
+            }
+            #[cfg(feature = "watch")]
+            Self::WatchFailed(_) => write!(f, "failed to watch the dylib"),
+            #[cfg(feature = "manifest")]
+            Self::ManifestParseFailed(_) => write!(f, "failed to parse the manifest"),
+            #[cfg(feature = "wasm")]
+            Self::WasmFailed(_) => write!(f, "WASM runtime error"),
+        }
+    }
+}
+
+impl std::error::Error for HotCrateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::LibraryLoadFailed(err) => Some(err),
+            Self::MetadataFailed(err) => Some(err),
+            Self::IoError(err) => Some(err),
+            Self::ObjectParseFailed(err) => Some(err),
+            Self::ReloadTimeout(err) => Some(err.as_ref()),
+            #[cfg(feature = "watch")]
+            Self::WatchFailed(err) => Some(err),
+            #[cfg(feature = "manifest")]
+            Self::ManifestParseFailed(err) => Some(err),
+            #[cfg(feature = "wasm")]
+            Self::WasmFailed(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// Displays an error followed by its [`source`](std::error::Error::source)s, such as
+/// `failed to load the dynamic library: <libloading error>: <OS error>`
+pub(crate) struct ErrorChain<'a>(pub &'a (dyn std::error::Error + 'static));
+
+impl fmt::Display for ErrorChain<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+
+        let mut source = self.0.source();
+        while let Some(err) = source {
+            write!(f, ": {}", err)?;
+            source = err.source();
+        }
+
+        Ok(())
+    }
+}
+
+impl From<libloading::Error> for HotCrateError {
+    fn from(err: libloading::Error) -> Self {
+        Self::LibraryLoadFailed(err)
+    }
+}
+
+impl From<cargo_metadata::Error> for HotCrateError {
+    fn from(err: cargo_metadata::Error) -> Self {
+        Self::MetadataFailed(err)
+    }
+}
+
+impl From<io::Error> for HotCrateError {
+    fn from(err: io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
+impl From<goblin::error::Error> for HotCrateError {
+    fn from(err: goblin::error::Error) -> Self {
+        Self::ObjectParseFailed(err)
+    }
+}
+
+#[cfg(feature = "watch")]
+impl From<notify::Error> for HotCrateError {
+    fn from(err: notify::Error) -> Self {
+        Self::WatchFailed(err)
+    }
+}
+
+#[cfg(feature = "manifest")]
+impl From<toml_edit::TomlError> for HotCrateError {
+    fn from(err: toml_edit::TomlError) -> Self {
+        Self::ManifestParseFailed(err)
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl From<wasmtime::Error> for HotCrateError {
+    fn from(err: wasmtime::Error) -> Self {
+        Self::WasmFailed(err)
+    }
+}
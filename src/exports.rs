@@ -0,0 +1,149 @@
+//! Symbol tables of dylib files
+
+use std::{collections::BTreeMap, fs};
+
+use goblin::Object;
+
+use crate::{HotCrate, HotCrateError, Result, Utf8Path};
+
+/// Difference of exported symbols between two dylibs. Created with [`HotCrate::diff_exports`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportDiff {
+    /// Symbols only exported by the new library
+    pub added: Vec<String>,
+    /// Symbols only exported by the old library
+    pub removed: Vec<String>,
+    /// Symbols exported by both libraries with different sizes. This is a heuristic; symbols
+    /// can change without changing their sizes.
+    pub changed: Vec<String>,
+}
+
+impl ExportDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Result of [`HotCrate::check_symbol_abi`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AbiReport {
+    /// The symbol has the expected size
+    Ok,
+    /// The symbol has another size, e.g. a `static` struct gained a field
+    SizeMismatch { found: usize, expected: usize },
+    /// The symbol table does not record the size (it's zero)
+    Unknown,
+}
+
+impl HotCrate {
+    /// Checks the size of an exported data symbol (`static` item) against `size_of::<T>()`
+    ///
+    /// See [`HotCrate::check_symbol_size`].
+    pub fn check_symbol_abi<T>(&self, symbol: &[u8]) -> Result<AbiReport> {
+        self.check_symbol_size(symbol, std::mem::size_of::<T>())
+    }
+
+    /// Checks the size of an exported symbol recorded in the symbol table of the loaded file.
+    /// Fails with [`HotCrateError::SymbolNotFound`] if the symbol is not exported.
+    ///
+    /// This is a diagnostic that catches the common case of a data symbol changing its size
+    /// between reloads, not all ABI breaks. The size of a function symbol is the size of its code.
+    /// Sizes are exact in ELF files and estimated from the symbol addresses in Mach-O and PE
+    /// files.
+    pub fn check_symbol_size(&self, symbol: &[u8], expected_size: usize) -> Result<AbiReport> {
+        let name = String::from_utf8_lossy(symbol);
+        let name = name.trim_end_matches('\0');
+
+        let exports = self::read_exports(&self.loaded_path)?;
+        let found = *exports
+            .get(name)
+            .ok_or_else(|| HotCrateError::SymbolNotFound(name.to_string()))?
+            as usize;
+
+        Ok(if found == 0 {
+            AbiReport::Unknown
+        } else if found == expected_size {
+            AbiReport::Ok
+        } else {
+            AbiReport::SizeMismatch {
+                found,
+                expected: expected_size,
+            }
+        })
+    }
+
+    /// Names of the symbols exported by the currently loaded file, sorted
+    ///
+    /// Useful for checking `#[no_mangle]` exports without running `nm`. Rust `dylib`s also export
+    /// mangled Rust symbols.
+    pub fn exports(&self) -> Result<Vec<String>> {
+        Ok(self::read_exports(&self.loaded_path)?.into_keys().collect())
+    }
+
+    /// Compares the exported symbols of the files loaded by `old` and `new`
+    ///
+    /// Keep the old library with [`HotCrate::try_clone`] to compare it with the reloaded one.
+    pub fn diff_exports(old: &HotCrate, new: &HotCrate) -> Result<ExportDiff> {
+        let old = self::read_exports(&old.loaded_path)?;
+        let new = self::read_exports(&new.loaded_path)?;
+
+        let mut diff = ExportDiff::default();
+
+        for (name, new_size) in &new {
+            match old.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(old_size) if old_size != new_size => diff.changed.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+
+        diff.removed = old
+            .keys()
+            .filter(|name| !new.contains_key(*name))
+            .cloned()
+            .collect();
+
+        Ok(diff)
+    }
+}
+
+/// Reads exported symbol names and sizes from an ELF, Mach-O or PE file
+pub(crate) fn read_exports(path: &Utf8Path) -> Result<BTreeMap<String, u64>> {
+    let bytes = fs::read(path)?;
+    let mut exports = BTreeMap::new();
+
+    match Object::parse(&bytes)? {
+        Object::Elf(elf) => {
+            for sym in elf.dynsyms.iter() {
+                // skip undefined (imported) and local symbols
+                if sym.st_shndx == 0 || sym.st_bind() == goblin::elf::sym::STB_LOCAL {
+                    continue;
+                }
+
+                if let Some(name) = elf.dynstrtab.get_at(sym.st_name) {
+                    if !name.is_empty() {
+                        exports.insert(name.to_string(), sym.st_size);
+                    }
+                }
+            }
+        }
+        Object::Mach(goblin::mach::Mach::Binary(macho)) => {
+            for export in macho.exports()? {
+                // C symbols are prefixed with `_` on macOS
+                let name = export.name.strip_prefix('_').unwrap_or(&export.name);
+                exports.insert(name.to_string(), export.size as u64);
+            }
+        }
+        Object::PE(pe) => {
+            for export in &pe.exports {
+                if let Some(name) = export.name {
+                    exports.insert(name.to_string(), export.size as u64);
+                }
+            }
+        }
+        _ => return Err(HotCrateError::UnsupportedObject(path.to_path_buf())),
+    }
+
+    Ok(exports)
+}
@@ -0,0 +1,40 @@
+//! Abstraction over [`HotCrate`] and [`MockHotCrate`](crate::MockHotCrate)
+
+use std::ffi::c_void;
+
+use crate::{HotCrate, Result};
+
+/// Common interface of [`HotCrate`] and [`MockHotCrate`](crate::MockHotCrate)
+///
+/// Write plugin consumers generic over this trait to test them without compiling a dylib.
+pub trait HotCrateExt {
+    /// Copies a pointer-sized symbol such as a function pointer
+    ///
+    /// # Safety
+    ///
+    /// `T` must match the actual type of the symbol. The value must not be used after reloading.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not pointer-sized.
+    unsafe fn get<T: Copy>(&self, symbol: &[u8]) -> Result<T>;
+
+    /// Reloads if the library is updated. Returns true if reloaded.
+    fn try_reload(&mut self) -> Result<bool>;
+}
+
+impl HotCrateExt for HotCrate {
+    unsafe fn get<T: Copy>(&self, symbol: &[u8]) -> Result<T> {
+        assert_eq!(
+            std::mem::size_of::<T>(),
+            std::mem::size_of::<*mut c_void>(),
+            "symbols must be pointer-sized"
+        );
+
+        Ok(*self.lib.get::<T>(symbol)?)
+    }
+
+    fn try_reload(&mut self) -> Result<bool> {
+        HotCrate::try_reload(self).map(|outcome| outcome.was_reloaded())
+    }
+}
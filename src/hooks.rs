@@ -0,0 +1,73 @@
+//! Callbacks around reloading
+
+use std::{fmt, sync::Arc};
+
+use libloading::Library;
+
+use crate::{HotCrate, Result, Utf8Path};
+
+type Hook = Arc<dyn Fn() + Send + Sync>;
+type Validator = Arc<dyn Fn(&Library) -> Result<()> + Send + Sync>;
+type CopyFn = dyn Fn(&Utf8Path, &Utf8Path) -> Result<()> + Send + Sync;
+
+/// Closure given to [`HotCrateBuilder::before_copy`](crate::HotCrateBuilder::before_copy)
+#[derive(Clone)]
+pub(crate) struct CopyHook(pub Arc<CopyFn>);
+
+impl fmt::Debug for CopyHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CopyHook(..)")
+    }
+}
+
+/// Hooks registered to a [`HotCrate`]
+#[derive(Clone, Default)]
+pub(crate) struct ReloadHooks {
+    pub before: Vec<Hook>,
+    pub after: Vec<Hook>,
+    /// Checks on the new library before it replaces the old one
+    pub validators: Vec<Validator>,
+    /// Replaces copying the build artifact to the temporary path
+    pub copy: Option<CopyHook>,
+}
+
+impl fmt::Debug for ReloadHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadHooks")
+            .field("before", &self.before.len())
+            .field("after", &self.after.len())
+            .field("validators", &self.validators.len())
+            .field("copy", &self.copy.is_some())
+            .finish()
+    }
+}
+
+impl ReloadHooks {
+    pub fn run_before(&self) {
+        self.before.iter().for_each(|hook| hook());
+    }
+
+    pub fn run_after(&self) {
+        self.after.iter().for_each(|hook| hook());
+    }
+
+    pub fn validate(&self, lib: &Library) -> Result<()> {
+        self.validators
+            .iter()
+            .try_for_each(|validator| validator(lib))
+    }
+}
+
+impl HotCrate {
+    /// Registers a callback called in [`HotCrate::force_reload`] before the old library is
+    /// dropped. Hooks are called in registration order.
+    pub fn on_before_reload(&mut self, hook: impl Fn() + Send + Sync + 'static) {
+        self.hooks.before.push(Arc::new(hook));
+    }
+
+    /// Registers a callback called in [`HotCrate::force_reload`] after the new library is loaded.
+    /// Hooks are called in registration order, only if the reload succeeds.
+    pub fn on_after_reload(&mut self, hook: impl Fn() + Send + Sync + 'static) {
+        self.hooks.after.push(Arc::new(hook));
+    }
+}
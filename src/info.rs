@@ -0,0 +1,37 @@
+//! Snapshot of the state of a [`HotCrate`]
+
+use std::{fs, time::SystemTime};
+
+use crate::{HotCrate, Utf8PathBuf};
+
+/// Snapshot of a [`HotCrate`] made with [`HotCrate::describe`], such as for health check endpoints
+/// or developer consoles
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HotCrateInfo {
+    pub package_name: String,
+    pub target_name: String,
+    /// Path of the build artifact
+    pub dylib_path: Utf8PathBuf,
+    /// Path of the currently loaded copy of the dylib
+    pub loaded_path: Utf8PathBuf,
+    pub reload_count: usize,
+    pub last_reloaded_at: Option<SystemTime>,
+    /// Size of the loaded dylib in bytes, if it's readable
+    pub file_size: Option<u64>,
+}
+
+impl HotCrate {
+    /// Makes a snapshot of the current state
+    pub fn describe(&self) -> HotCrateInfo {
+        HotCrateInfo {
+            package_name: self.package_name().to_string(),
+            target_name: self.target_name().to_string(),
+            dylib_path: self.build_artifact_path().to_path_buf(),
+            loaded_path: self.loaded_path().to_path_buf(),
+            reload_count: self.reload_count(),
+            last_reloaded_at: self.last_reloaded_at(),
+            file_size: fs::metadata(self.loaded_path()).ok().map(|m| m.len()),
+        }
+    }
+}
@@ -16,76 +16,459 @@ pub extern crate libloading;
 pub use cargo_metadata::camino::{self, Utf8Path, Utf8PathBuf};
 pub use libloading::Symbol;
 
-use cargo_metadata::{Metadata, MetadataCommand, Package, Target};
-use libloading::Library;
+/// `tracing::debug!` with the `tracing` feature, `log::debug!` otherwise
+macro_rules! debug_event {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        log::debug!($($arg)*);
+    }};
+}
+
+/// `unsafe` [`HotCrate::get`] with the symbol name as an identifier, returning
+/// [`Result`]`<`[`Symbol`]`<fn_type>>`
+///
+/// ```ignore
+/// let add = unsafe { hot_crate::load_symbol!(plugin, add, extern "C" fn(i32, i32) -> i32)? };
+/// ```
+#[cfg(feature = "export_macros")]
+#[macro_export]
+macro_rules! load_symbol {
+    ($hot:expr, $symbol:ident, $ty:ty) => {
+        $hot.get::<$ty>(::std::stringify!($symbol).as_bytes())
+            .map_err($crate::HotCrateError::from)
+    };
+}
 
-use std::{fs, time::SystemTime};
+#[cfg(feature = "tokio")]
+mod async_reload;
+mod batch;
+mod builder;
+mod call;
+mod change;
+mod cleanup;
+mod config;
+mod deps;
+mod error;
+mod exports;
+mod ext;
+mod hooks;
+mod info;
+mod lock_file;
+#[cfg(feature = "manifest")]
+mod manifest;
+mod mock;
+mod packages;
+mod pin;
+mod plugin_metadata;
+mod pool;
+mod rebuild;
+mod registry;
+mod snapshot;
+mod state;
+mod stats;
+mod status;
+mod strategy;
+mod symbol;
+mod versioned;
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "watch")]
+mod watch;
 
-/// TODO: create error type
-pub type Error = Box<dyn std::error::Error>;
+pub use builder::HotCrateBuilder;
+pub use change::ChangeDetection;
+pub use config::HotCrateConfig;
+pub use error::{HotCrateError, Result};
+pub use exports::{AbiReport, ExportDiff};
+pub use ext::HotCrateExt;
+pub use info::HotCrateInfo;
+#[cfg(feature = "manifest")]
+pub use manifest::{patch_manifest_for_hot_reload, revert_manifest_patch};
+pub use mock::MockHotCrate;
+pub use packages::{find_all_dylib_paths, DylibEntry, DylibPackageInfo};
+pub use pin::PinnedHotCrate;
+pub use plugin_metadata::PluginMetadata;
+pub use pool::HotCratePool;
+pub use rebuild::{BuildOptions, NetworkAccess};
+pub use registry::{HotCrateRegistry, RegistryRef};
+pub use snapshot::Snapshot;
+pub use state::{
+    read_state, write_state, HotDeserializeFn, HotSerializeFn, StateTransfer, StateWriter,
+};
+pub use stats::ReloadRecord;
+pub use status::{ReloadOutcome, ReloadStatus};
+pub use strategy::ReloadStrategy;
+pub use symbol::{CachedSymbol, SharedSymbol, SymbolGuard};
+pub use versioned::VersionedHotCrate;
+#[cfg(feature = "wasm")]
+pub use wasm::WasmHotCrate;
+#[cfg(feature = "wasm")]
+pub use wasmtime;
+#[cfg(feature = "watch")]
+pub use watch::{ReloadEvent, WatchAndRebuildHandle, WatchHandle, Watcher};
 
-/// TODO: create error type
-pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+use builder::ConfigureMetadata;
+use cargo_metadata::{Metadata, MetadataCommand, Package, Target};
+use libloading::Library;
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
 
 /// macOS: `dylib`, Linux: `so`, Windows: `dll`
 #[cfg(target_os = "macos")]
-const DYLIB_EXTENSION: &'static str = "dylib";
+const DYLIB_EXTENSION: &str = "dylib";
 
 /// macOS: `dylib`, Linux: `so`, Windows: `dll`
 #[cfg(target_os = "linux")]
-const DYLIB_EXTENSION: &'static str = "so";
+const DYLIB_EXTENSION: &str = "so";
+
+/// macOS: `dylib`, Linux and BSDs: `so`, Windows: `dll`
+#[cfg(any(target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+const DYLIB_EXTENSION: &str = "so";
+
+/// macOS: `dylib`, Linux, Solaris and illumos: `so`, Windows: `dll`
+#[cfg(any(target_os = "solaris", target_os = "illumos"))]
+const DYLIB_EXTENSION: &str = "so";
 
 /// macOS: `dylib`, Linux: `so`, Windows: `dll`
-#[cfg(target_os = "window")]
-const DYLIB_EXTENSION: &'static str = "dll";
+#[cfg(target_os = "windows")]
+const DYLIB_EXTENSION: &str = "dll";
+
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "linux",
+    target_os = "windows",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "solaris",
+    target_os = "illumos",
+)))]
+compile_error!("hot_crate does not know the dylib file extension of the target OS");
+
+/// macOS, Linux: `lib`, Windows: none
+#[cfg(not(target_os = "windows"))]
+const DYLIB_PREFIX: &str = "lib";
+
+/// macOS, Linux: `lib`, Windows: none
+#[cfg(target_os = "windows")]
+const DYLIB_PREFIX: &str = "";
 
 /// A reloadable dynamic [`Library`]
 #[derive(Debug)]
 pub struct HotCrate {
-    main_metadata: Metadata,
-    dylib_toml: Utf8PathBuf,
+    /// `None` if loaded from a path with [`HotCrate::load_path`]
+    main_metadata: Option<Metadata>,
+    /// Manifest given to the builder, or the workspace root manifest if the metadata is given.
+    /// `None` if loaded from a path with [`HotCrate::load_path`].
+    main_toml: Option<Utf8PathBuf>,
+    /// `None` if loaded from a path with [`HotCrate::load_path`]
+    dylib_toml: Option<Utf8PathBuf>,
+    /// Canonical manifest path of the `dylib` package in the cargo metadata, compared instead of
+    /// `dylib_toml` (which can be relative or go through symlinks). `None` if loaded from a path
+    /// with [`HotCrate::load_path`].
+    pkg_manifest: Option<Utf8PathBuf>,
+    package_name: String,
+    target_name: String,
     /// API to load symbols from the target `dylib` crate
-    lib: Library,
+    ///
+    /// Shared with [`SymbolGuard`]s and [`SharedSymbol`]s, which keep the library alive even after
+    /// reloading.
+    lib: Arc<Library>,
+    /// Shared with [`SymbolGuard`]s, which block reloading
+    guards: Arc<()>,
+    /// Shared with [`PinnedHotCrate`]s, which block reloading
+    pins: Arc<()>,
     lib_path: Utf8PathBuf,
+    /// Path of the file actually loaded. Temporary copy of `lib_path`, except after
+    /// `HotCrate::force_reload_in_place` on Linux.
+    loaded_path: Utf8PathBuf,
+    /// Removes the temporary copies on drop. Declared after `lib` so that it's dropped later.
+    temp_copies: cleanup::TempCopies,
     /// See [`fs::Metadata::modified`][f]
     ///
     /// [f]: https://doc.rust-lang.org/std/fs/struct.Metadata.html#method.modified
     lib_timestamp: Option<SystemTime>,
-    /// TODO: remove counter and use something like uuid?
-    reload_counter: usize,
+    change_detection: ChangeDetection,
+    /// SHA-256 of the loaded file in [`ChangeDetection::Sha256Hash`] mode
+    #[cfg(feature = "checksum")]
+    lib_hash: Option<[u8; 32]>,
+    /// Hash of the loaded file, used instead of the modification time if the platform does not
+    /// support it
+    lib_content_hash: Option<u64>,
+    /// Number of successful reloads
+    reload_count: usize,
+    /// Set on every successful reload (not on the initial load)
+    last_reloaded_at: Option<SystemTime>,
+    /// Time spent on copying and opening the dylib in [`HotCrate::force_reload`]
+    total_reload_duration: Duration,
+    /// Number of reloads measured in `total_reload_duration`
+    timed_reloads: u32,
+    /// See [`HotCrate::reload_stats`]
+    reload_stats: stats::ReloadStats,
+    /// See [`HotCrate::try_reload_nonblocking`]
+    build_noticed_at: Option<Instant>,
+    /// `${TMP_DIR}/hot_crate` by default
+    temp_dir: Utf8PathBuf,
+    reload_strategy: ReloadStrategy,
+    /// `install_name_tool` run on macOS, or `None` to skip it
+    install_name_tool: Option<PathBuf>,
+    /// Cargo profile name override
+    profile: Option<String>,
+    /// `--target` of cargo when cross-compiling
+    target_triple: Option<String>,
+    /// Minimum age of the dylib file to be reloaded
+    debounce: Duration,
+    /// Symbols loaded with [`HotCrate::get_cached`]
+    symbol_cache: Mutex<HashMap<Vec<u8>, symbol::SymbolAddress>>,
+    /// Environment variables set while opening the dylib. See [`HotCrate::load_with_env`].
+    load_env: HashMap<String, String>,
+    hooks: hooks::ReloadHooks,
+    build_options: BuildOptions,
+    /// See [`HotCrateBuilder::enable_lock_file`]
+    lock_file: Option<Utf8PathBuf>,
+    configure_metadata: Option<ConfigureMetadata>,
+}
+
+/// Re-opens the currently loaded file. See [`HotCrate::try_clone`].
+///
+/// # Panics
+///
+/// Panics if the library fails to be re-opened.
+impl Clone for HotCrate {
+    fn clone(&self) -> Self {
+        self.try_clone()
+            .unwrap_or_else(|err| panic!("failed to clone `{}`: {}", self.package_name, err))
+    }
 }
 
-unsafe impl Send for HotCrate {}
-unsafe impl Sync for HotCrate {}
+/// Compares the `dylib` package manifest as resolved by cargo metadata (or the dylib path if
+/// loaded with [`HotCrate::load_path`]) and the target name
+impl PartialEq for HotCrate {
+    fn eq(&self, other: &Self) -> bool {
+        self.identity() == other.identity()
+    }
+}
+
+impl Eq for HotCrate {}
+
+impl Hash for HotCrate {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.identity().hash(state);
+    }
+}
+
+/// `HotCrate(plugin, reloaded 3 times, loaded from /tmp/hot_crate/plugin/libplugin-<uuid>.so)`
+impl fmt::Display for HotCrate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "HotCrate({}, reloaded {} time{}, loaded from {})",
+            self.package_name,
+            self.reload_count,
+            if self.reload_count == 1 { "" } else { "s" },
+            self.loaded_path
+        )
+    }
+}
 
 impl HotCrate {
     /// Loads a `dylib` crate
     ///
     /// See [`Library::new`] for thread safety. Arguments are in absolute paths.
     pub fn load(main_toml: impl AsRef<Utf8Path>, dylib_toml: impl AsRef<Utf8Path>) -> Result<Self> {
-        let main_toml = main_toml.as_ref();
+        Self::builder()
+            .main_toml(main_toml.as_ref())
+            .dylib_toml(dylib_toml.as_ref())
+            .build()
+    }
+
+    /// [`HotCrate::load`] setting the environment variables in `env` while opening the dylib
+    ///
+    /// Some dylibs read environment variables when they're loaded, such as to find other shared
+    /// libraries. The variables are set only during the call to [`Library::new`] and restored
+    /// afterward, also on every reload. The change is serialized with the other environment
+    /// changes of `hot_crate`, but not with `std::env::set_var` or `std::env::var` called by other
+    /// threads.
+    pub fn load_with_env(
+        main_toml: impl AsRef<Utf8Path>,
+        dylib_toml: impl AsRef<Utf8Path>,
+        env: HashMap<String, String>,
+    ) -> Result<Self> {
+        Self::builder()
+            .main_toml(main_toml.as_ref())
+            .dylib_toml(dylib_toml.as_ref())
+            .load_env(env)
+            .build()
+    }
+
+    /// [`HotCrate::load`] checking that the dylib exports all the `required_symbols`, before any
+    /// `unsafe` use of them
+    ///
+    /// Fails with [`HotCrateError::MissingRequiredSymbol`] for the first missing symbol. Reloaded
+    /// libraries are not checked.
+    pub fn load_checked(
+        main_toml: impl AsRef<Utf8Path>,
+        dylib_toml: impl AsRef<Utf8Path>,
+        required_symbols: &[&[u8]],
+    ) -> Result<Self> {
+        let hot = Self::load(main_toml, dylib_toml)?;
+
+        if let Some(missing) = required_symbols.iter().find(|s| !hot.has_symbol(s)) {
+            let name = String::from_utf8_lossy(missing);
+            return Err(HotCrateError::MissingRequiredSymbol(
+                name.trim_end_matches('\0').to_string(),
+            ));
+        }
+
+        Ok(hot)
+    }
+
+    /// Loads a `dylib` crate reusing the metadata of the main crate, which skips running
+    /// `cargo metadata`
+    pub fn load_with_metadata(
+        metadata: Metadata,
+        dylib_toml: impl AsRef<Utf8Path>,
+    ) -> Result<Self> {
+        Self::builder()
+            .metadata(metadata)
+            .dylib_toml(dylib_toml.as_ref())
+            .build()
+    }
+
+    /// Loads a `dylib` crate in the workspace discovered from the `dylib` manifest
+    ///
+    /// Cargo looks for the workspace root by walking up the directories, so the main crate does
+    /// not have to be specified. A package outside any workspace is treated as its own root.
+    pub fn load_default(dylib_toml: impl AsRef<Utf8Path>) -> Result<Self> {
         let dylib_toml = dylib_toml.as_ref();
+        let metadata = MetadataCommand::new().manifest_path(dylib_toml).exec()?;
+        Self::load_with_metadata(metadata, dylib_toml)
+    }
 
-        let main_metadata = MetadataCommand::new().manifest_path(main_toml).exec()?;
-        let lib_path = self::find_dylib_path(&main_metadata, dylib_toml)?;
-        let lib = unsafe { Library::new(&lib_path)? };
-        let lib_timestamp = fs::metadata(&lib_path)?.modified().ok();
+    /// Loads every `dylib` or `cdylib` target of the package, running `cargo metadata` once
+    pub fn load_all_targets(
+        main_toml: impl AsRef<Utf8Path>,
+        dylib_toml: impl AsRef<Utf8Path>,
+    ) -> Result<Vec<Self>> {
+        let dylib_toml = dylib_toml.as_ref();
+        let metadata = MetadataCommand::new()
+            .manifest_path(main_toml.as_ref())
+            .exec()?;
+
+        self::find_all_dylib_targets(&metadata, dylib_toml)?
+            .into_iter()
+            .map(|target| {
+                Self::builder()
+                    .metadata(metadata.clone())
+                    .dylib_toml(dylib_toml)
+                    .target_name(&target.name)
+                    .build()
+            })
+            .collect()
+    }
+
+    /// Loads a prebuilt dylib file without cargo metadata
+    ///
+    /// [`HotCrate::try_reload`] watches the modification time of the file and
+    /// [`HotCrate::force_reload`] reloads from the same path.
+    pub fn load_path(dylib_path: impl AsRef<Utf8Path>) -> Result<Self> {
+        Self::builder().dylib_path(dylib_path.as_ref()).build()
+    }
+
+    /// Configures a `dylib` crate before loading it
+    pub fn builder() -> HotCrateBuilder {
+        HotCrateBuilder::new()
+    }
+
+    /// Creates an independent handle by opening [`HotCrate::loaded_path`] again
+    ///
+    /// The clone starts with the same reload count and hooks, and reloads independently of the
+    /// original. A temporary copy is copied again for the clone, so that either can be dropped
+    /// without removing the file loaded by the other.
+    pub fn try_clone(&self) -> Result<Self> {
+        let mut temp_copies = cleanup::TempCopies::new(
+            &self.temp_dir,
+            &self.package_name,
+            &self.target_name,
+            self.temp_copies.max_copies,
+        );
+
+        let loaded_path = if self.loaded_path == self.lib_path {
+            self.loaded_path.clone()
+        } else {
+            let path = self.tmp_dylib_path();
+            fs::copy(&self.loaded_path, &path)?;
+            temp_copies.push(path.clone());
+            path
+        };
+
+        let lib =
+            Arc::new(unsafe { deps::open_library(&loaded_path, &self.lib_path, &self.load_env)? });
 
         Ok(Self {
-            main_metadata,
-            dylib_toml: dylib_toml.to_path_buf(),
+            main_metadata: self.main_metadata.clone(),
+            main_toml: self.main_toml.clone(),
+            dylib_toml: self.dylib_toml.clone(),
+            pkg_manifest: self.pkg_manifest.clone(),
+            package_name: self.package_name.clone(),
+            target_name: self.target_name.clone(),
             lib,
-            lib_path,
-            lib_timestamp,
-            reload_counter: 0,
+            guards: Default::default(),
+            pins: Default::default(),
+            lib_path: self.lib_path.clone(),
+            loaded_path,
+            temp_copies,
+            lib_timestamp: self.lib_timestamp,
+            change_detection: self.change_detection,
+            #[cfg(feature = "checksum")]
+            lib_hash: self.lib_hash,
+            lib_content_hash: self.lib_content_hash,
+            reload_count: self.reload_count,
+            last_reloaded_at: self.last_reloaded_at,
+            total_reload_duration: self.total_reload_duration,
+            timed_reloads: self.timed_reloads,
+            reload_stats: self.reload_stats.clone(),
+            build_noticed_at: self.build_noticed_at,
+            temp_dir: self.temp_dir.clone(),
+            reload_strategy: self.reload_strategy,
+            install_name_tool: self.install_name_tool.clone(),
+            profile: self.profile.clone(),
+            target_triple: self.target_triple.clone(),
+            debounce: self.debounce,
+            symbol_cache: Default::default(),
+            load_env: self.load_env.clone(),
+            hooks: self.hooks.clone(),
+            build_options: self.build_options.clone(),
+            lock_file: self.lock_file.clone(),
+            configure_metadata: self.configure_metadata.clone(),
         })
     }
 
     /// See [`libloading::Library::close`]
+    ///
+    /// If any [`SymbolGuard`] or [`SharedSymbol`] is alive, the library is closed when the last of
+    /// them is dropped.
     pub fn unload(self) -> std::result::Result<(), libloading::Error> {
-        self.lib.close()
+        match Arc::try_unwrap(self.lib) {
+            Ok(lib) => lib.close(),
+            Err(_shared) => Ok(()),
+        }
     }
 
+    /// See [`Library::get`]
+    ///
+    /// # Safety
+    ///
+    /// `T` must match the actual type of the symbol.
     pub unsafe fn get<'lib, T>(
         &'lib self,
         symbol: &[u8],
@@ -93,109 +476,709 @@ impl HotCrate {
         self.lib.get(symbol)
     }
 
-    fn tmp_dylib_path(&mut self) -> Result<Utf8PathBuf> {
-        let pkg = self::find_dylib_pkg(&self.main_metadata, &self.dylib_toml)?;
-        let target = self::find_dylib_target(&self.main_metadata, &self.dylib_toml)?;
-
-        // ${TMP_DIR}/hot_crate/lib${plugin}-${counter}.${ext}
-        let tmp = Utf8PathBuf::from_path_buf(std::env::temp_dir())
-            .map_err(|p| format!("unable to create UTF8 path from {}", p.display()))?;
-        let tmp = tmp.join("hot_crate").join(format!("{}", pkg.name));
-        let tmp = tmp.join(format!(
-            "lib{}-{}.{}",
-            target.name, self.reload_counter, DYLIB_EXTENSION,
-        ));
-
-        self.reload_counter += 1;
+    /// See the `PartialEq` implementation
+    fn identity(&self) -> (&Utf8Path, &str) {
+        let path = self.pkg_manifest.as_deref().unwrap_or(&self.lib_path);
+        (path, &self.target_name)
+    }
 
-        Ok(tmp)
+    fn tmp_dylib_path(&self) -> Utf8PathBuf {
+        self.placement().tmp_dylib_path()
     }
 
     pub fn lib(&self) -> &Library {
         &self.lib
     }
 
-    /// Reloads the dylib if it's outdated. Returns true if succeed in reloading.
-    pub fn try_reload(&mut self) -> Result<bool> {
-        let timestamp = fs::metadata(&self.lib_path)?.modified().ok();
+    /// Name of the `dylib` package. The file name without the prefix and the extension if loaded
+    /// with [`HotCrate::load_path`].
+    pub fn package_name(&self) -> &str {
+        &self.package_name
+    }
+
+    /// Name of the `dylib` or `cdylib` target. Same as [`HotCrate::package_name`] if loaded with
+    /// [`HotCrate::load_path`].
+    pub fn target_name(&self) -> &str {
+        &self.target_name
+    }
+
+    /// Cargo metadata of the main crate. `None` if loaded with [`HotCrate::load_path`].
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.main_metadata.as_ref()
+    }
+
+    /// Manifest of the main crate: the one given to [`HotCrateBuilder::main_toml`], or the
+    /// workspace root manifest if the metadata is given. `None` if loaded with
+    /// [`HotCrate::load_path`].
+    pub fn main_toml(&self) -> Option<&Utf8Path> {
+        self.main_toml.as_deref()
+    }
+
+    /// Manifest of the `dylib` crate. `None` if loaded with [`HotCrate::load_path`].
+    pub fn dylib_toml(&self) -> Option<&Utf8Path> {
+        self.dylib_toml.as_deref()
+    }
+
+    /// Cargo target directory, respecting `CARGO_TARGET_DIR` and `build.target-dir`. `None` if
+    /// loaded with [`HotCrate::load_path`].
+    pub fn target_dir(&self) -> Option<&Utf8Path> {
+        self.main_metadata
+            .as_ref()
+            .map(|metadata| metadata.target_directory.as_path())
+    }
+
+    /// Runs `cargo metadata` again and updates the build artifact path
+    ///
+    /// Fails with [`HotCrateError::NoMetadata`] if loaded with [`HotCrate::load_path`].
+    pub fn refresh_metadata(&mut self) -> Result<()> {
+        let (main_toml, dylib_toml) = match (&self.main_toml, &self.dylib_toml) {
+            (Some(m), Some(d)) => (m, d),
+            _ => return Err(HotCrateError::NoMetadata),
+        };
+
+        let main_metadata =
+            ConfigureMetadata::command(self.configure_metadata.as_ref(), main_toml).exec()?;
+
+        let pkg = self::find_dylib_pkg(&main_metadata, dylib_toml)?;
+        let target = self::find_dylib_target(&main_metadata, dylib_toml, Some(&self.target_name))?;
+        self.package_name = pkg.name.clone();
+        self.lib_path = self::find_dylib_path(
+            &main_metadata,
+            target,
+            self.profile.as_deref(),
+            self.target_triple.as_deref(),
+        )?;
+        self.main_metadata = Some(main_metadata);
+
+        Ok(())
+    }
+
+    /// Path of the build artifact in the Cargo `target/` directory
+    ///
+    /// This is the file to watch for changes; it's not the temporary copy that is actually loaded
+    /// (see [`HotCrate::loaded_path`]).
+    pub fn build_artifact_path(&self) -> &Utf8Path {
+        &self.lib_path
+    }
+
+    /// Current modification time of the build artifact, which is compared with the loaded one by
+    /// [`HotCrate::try_reload`]. `None` if the platform does not support it.
+    pub fn build_artifact_modified(&self) -> Result<Option<SystemTime>> {
+        Ok(fs::metadata(&self.lib_path)?.modified().ok())
+    }
+
+    /// Size of the build artifact in bytes, such as for printing "reloaded plugin (12.3 MB)"
+    ///
+    /// The loaded temporary copy has the same size unless the dylib is rebuilt after loading.
+    pub fn lib_file_size(&self) -> Result<u64> {
+        Ok(fs::metadata(&self.lib_path)?.len())
+    }
+
+    /// Path of the file actually loaded as the [`Library`]
+    ///
+    /// This is a temporary copy of the build artifact, also on the initial load, so that the build
+    /// artifact is not locked on Windows.
+    pub fn loaded_path(&self) -> &Utf8Path {
+        &self.loaded_path
+    }
+
+    /// Number of successful reloads, starting from [`HotCrateBuilder::reload_count`]
+    pub fn reload_count(&self) -> usize {
+        self.reload_count
+    }
 
-        if timestamp == self.lib_timestamp {
-            Ok(false)
+    /// When the dylib was reloaded last time. `None` until the first reload.
+    pub fn last_reloaded_at(&self) -> Option<SystemTime> {
+        self.last_reloaded_at
+    }
+
+    /// Total time spent on reloading, from copying the dylib to opening it (including
+    /// `install_name_tool` on macOS)
+    pub fn total_reload_duration(&self) -> Duration {
+        self.total_reload_duration
+    }
+
+    /// Average of [`HotCrate::total_reload_duration`]. `None` until the first reload.
+    pub fn avg_reload_duration(&self) -> Option<Duration> {
+        if self.timed_reloads == 0 {
+            None
         } else {
-            self.force_reload()?;
-            Ok(true)
+            Some(self.total_reload_duration / self.timed_reloads)
+        }
+    }
+
+    /// Reloads the dylib if it's outdated
+    ///
+    /// The dylib is not reloaded while it was modified within the debounce duration (see
+    /// [`HotCrateBuilder::debounce`]), since the linker may be still writing it.
+    pub fn try_reload(&mut self) -> Result<ReloadOutcome> {
+        if !self.is_stale()? {
+            return Ok(ReloadOutcome::Unchanged);
+        }
+
+        let start = Instant::now();
+        self.force_reload()?;
+
+        Ok(ReloadOutcome::Reloaded {
+            reload_count: self.reload_count,
+            duration: start.elapsed(),
+        })
+    }
+
+    /// [`HotCrate::try_reload`] only if the build artifact was modified within the last
+    /// `duration`, such as for reload-on-save workflows. Returns false if the modification time
+    /// is not available on the platform.
+    pub fn reload_if_older_than(&mut self, duration: Duration) -> Result<bool> {
+        let recent = match self.build_artifact_modified()? {
+            Some(modified) => modified
+                .elapsed()
+                .map_or(true, |elapsed| elapsed < duration),
+            None => false,
+        };
+
+        if !recent {
+            return Ok(false);
+        }
+
+        Ok(self.try_reload()?.was_reloaded())
+    }
+
+    /// [`HotCrate::try_reload`] retrying while the dylib can't be opened, such as when a parallel
+    /// `cargo build` is still writing it
+    ///
+    /// Retries with exponential backoff (from 10ms up to 1s) and fails with
+    /// [`HotCrateError::ReloadTimeout`] holding the last error after `timeout`. Other errors,
+    /// including rejections by validators such as the one of
+    /// [`VersionedHotCrate`](crate::VersionedHotCrate), are returned immediately. The
+    /// [`HotCrate::on_before_reload`] hooks are called once.
+    pub fn try_reload_with_timeout(&mut self, timeout: Duration) -> Result<bool> {
+        if !self.is_stale()? {
+            return Ok(false);
+        }
+
+        self.check_unlocked()?;
+        let _span = self.reload_span();
+        self.hooks.run_before();
+
+        let start = Instant::now();
+        let started_at = SystemTime::now();
+        let mut interval = Duration::from_millis(10);
+
+        loop {
+            let err = match self.open_copy() {
+                Ok(copy) => {
+                    self.swap_copy(copy, started_at, start)?;
+                    return Ok(true);
+                }
+                Err(err @ HotCrateError::LibraryLoadFailed(_)) => err,
+                Err(err) => return Err(err),
+            };
+
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Err(HotCrateError::ReloadTimeout(Box::new(err)));
+            }
+
+            debug_event!("retrying to reload `{}`: {}", self.lib_path, err);
+            std::thread::sleep(interval.min(timeout - elapsed));
+            interval = (interval * 2).min(Duration::from_secs(1));
+        }
+    }
+
+    /// Whether [`HotCrate::try_reload`] would reload the dylib now, without reloading it
+    pub fn is_stale(&self) -> Result<bool> {
+        let timestamp = self.build_artifact_modified()?;
+        self.should_reload(timestamp)
+    }
+
+    /// Always true. A [`HotCrate`] holds a loaded library from construction, even while
+    /// reloading fails.
+    pub fn is_loaded(&self) -> bool {
+        true
+    }
+
+    /// Whether the build artifact with the modification time is worth reloading
+    fn should_reload(&self, timestamp: Option<SystemTime>) -> Result<bool> {
+        if let Some(elapsed) = timestamp.and_then(|t| t.elapsed().ok()) {
+            if elapsed < self.debounce {
+                return Ok(false);
+            }
+        }
+
+        self.has_changed(timestamp)
+    }
+
+    /// Whether the build artifact with the modification time differs from the loaded one,
+    /// ignoring the debounce (used after `cargo build` has finished writing it)
+    pub(crate) fn has_changed(&self, timestamp: Option<SystemTime>) -> Result<bool> {
+        let changed = match self.change_detection {
+            // fall back to hashes if modification times are not supported
+            ChangeDetection::Mtime if timestamp.is_none() => {
+                Some(change::content_hash(&self.lib_path)?) != self.lib_content_hash
+            }
+            ChangeDetection::Mtime => timestamp != self.lib_timestamp,
+            #[cfg(feature = "checksum")]
+            ChangeDetection::Sha256Hash => {
+                Some(change::sha256_file(&self.lib_path)?) != self.lib_hash
+            }
+        };
+
+        Ok(changed)
+    }
+
+    /// Reloads from another dylib file, which is watched by [`HotCrate::try_reload`] afterwards
+    ///
+    /// Useful for loading prebuilt dylibs or rolling back to a previous copy (see
+    /// [`HotCrate::loaded_path`]). The build artifact path is kept on failure.
+    pub fn force_reload_from_path(&mut self, path: &Utf8Path) -> Result<()> {
+        let old_path = std::mem::replace(&mut self.lib_path, path.to_path_buf());
+
+        let result = self.force_reload();
+        if result.is_err() {
+            self.lib_path = old_path;
         }
+
+        result
     }
 
     /// Reloads the dylib anyways
+    ///
+    /// Fails with [`HotCrateError::Busy`] while any [`SymbolGuard`] is alive, and with
+    /// [`HotCrateError::Pinned`] while any [`PinnedHotCrate`] is alive. Libraries referred to by
+    /// [`SharedSymbol`]s are kept open until the symbols are dropped.
+    ///
+    /// Plugin state is transferred if the dylib exports `hot_serialize` and `hot_deserialize`
+    /// (see [`StateTransfer`]).
     pub fn force_reload(&mut self) -> Result<()> {
-        {
-            let dylib_pkg = self::find_dylib_pkg(&self.main_metadata, &self.dylib_toml)?;
-            log::info!("reloading library `{}`..", dylib_pkg.name);
+        self.check_unlocked()?;
+        let _span = self.reload_span();
+        self.hooks.run_before();
+
+        let start = Instant::now();
+        let started_at = SystemTime::now();
+
+        let copy = self.open_copy()?;
+        self.swap_copy(copy, started_at, start)
+    }
+
+    /// [`HotCrate::force_reload`] opening the build artifact directly, without the temporary copy
+    /// and `install_name_tool`
+    ///
+    /// Linux only. `dlopen` maps the file through its own file descriptor, and cargo replaces the
+    /// build artifact with a new file rather than overwriting it, so the loaded library keeps the
+    /// old inode alive. It's unsafe on macOS, where dyld can return the old library for the same
+    /// path or install name, so it's not provided there.
+    ///
+    /// The dynamic loader returns the library already opened from the same path, so this falls
+    /// back to [`HotCrate::force_reload`] if the build artifact is still open in the process, such
+    /// as after the previous in-place reload, or by a clone or a [`SharedSymbol`] made before it.
+    #[cfg(target_os = "linux")]
+    pub fn force_reload_in_place(&mut self) -> Result<()> {
+        if deps::is_open(&self.lib_path) {
+            debug_event!("`{}` is open; reloading a copy of it", self.lib_path);
+            return self.force_reload();
         }
 
-        let dylib_path = self::find_dylib_path(&self.main_metadata, &self.dylib_toml)?;
-        let tmp_dylib_path = self.tmp_dylib_path()?;
-        let tmp_dir = tmp_dylib_path.parent().unwrap();
+        self.check_unlocked()?;
+
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::info_span!("hot_crate::force_reload_in_place", pkg = %self.package_name)
+                .entered();
+        #[cfg(not(feature = "tracing"))]
+        log::info!("reloading library `{}` in place..", self.package_name);
 
-        // Copy the dylib to the tmp location.
-        fs::create_dir_all(&tmp_dir)?;
-        fs::copy(&dylib_path, &tmp_dylib_path)?;
+        self.hooks.run_before();
 
-        if cfg!(target_os = "macos") {
-            std::process::Command::new("install_name_tool")
-                .current_dir(&tmp_dir)
-                .arg("-id")
-                .arg("''")
-                .arg(tmp_dylib_path.file_name().unwrap())
-                .output()
-                .expect("`install_name_tool` failed to start");
+        let start = Instant::now();
+        let started_at = SystemTime::now();
+
+        let new_lib =
+            unsafe { deps::open_library(&self.lib_path, &self.lib_path, &self.load_env)? };
+        let load_duration = start.elapsed();
+        self.hooks.validate(&new_lib)?;
+
+        let lib_path = self.lib_path.clone();
+        self.swap_library(
+            new_lib,
+            lib_path,
+            started_at,
+            start,
+            Duration::ZERO,
+            load_duration,
+        )
+    }
+
+    /// Enters the `hot_crate::force_reload` span, or logs the reload without the `tracing` feature
+    #[cfg(feature = "tracing")]
+    fn reload_span(&self) -> tracing::span::EnteredSpan {
+        tracing::info_span!("hot_crate::force_reload", pkg = %self.package_name).entered()
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    fn reload_span(&self) -> ReloadSpan {
+        log::info!("reloading library `{}`..", self.package_name);
+        ReloadSpan
+    }
+
+    /// Places a temporary copy of the build artifact and opens it. The copy is removed on failure.
+    fn open_copy(&self) -> Result<OpenedCopy> {
+        let start = Instant::now();
+        let path = self.placement().place(&self.lib_path)?;
+        let copy_duration = start.elapsed();
+
+        let load_start = Instant::now();
+        match unsafe { deps::open_library(&path, &self.lib_path, &self.load_env) } {
+            Ok(lib) => Ok(OpenedCopy {
+                lib,
+                path,
+                copy_duration,
+                load_duration: load_start.elapsed(),
+            }),
+            Err(err) => {
+                self::remove_copy(&path);
+                Err(err)
+            }
         }
+    }
 
-        self.lib = unsafe { Library::new(&tmp_dylib_path)? };
-        self.lib_path = dylib_path;
-        self.lib_timestamp = fs::metadata(&self.lib_path)?.modified().ok();
+    /// Validates the opened copy and swaps the library with it. The copy is removed if it's
+    /// rejected.
+    fn swap_copy(
+        &mut self,
+        copy: OpenedCopy,
+        started_at: SystemTime,
+        start: Instant,
+    ) -> Result<()> {
+        if let Err(err) = self.hooks.validate(&copy.lib) {
+            drop(copy.lib);
+            self::remove_copy(&copy.path);
+            return Err(err);
+        }
+
+        self.swap_library(
+            copy.lib,
+            copy.path,
+            started_at,
+            start,
+            copy.copy_duration,
+            copy.load_duration,
+        )
+    }
+
+    /// Replaces the library with `new_lib` opened from `loaded_path`, transferring the plugin
+    /// state, and updates the reload records
+    fn swap_library(
+        &mut self,
+        new_lib: Library,
+        loaded_path: Utf8PathBuf,
+        started_at: SystemTime,
+        start: Instant,
+        copy_duration: Duration,
+        load_duration: Duration,
+    ) -> Result<()> {
+        let state = state::serialize(&self.lib);
+        self.lib = Arc::new(new_lib);
+        if let Some(ref state) = state {
+            state::deserialize(&self.lib, state);
+        }
+        debug_event!("opened `{}`", loaded_path);
+        let total_duration = start.elapsed();
+        self.total_reload_duration += total_duration;
+        self.timed_reloads += 1;
+        self.reload_stats.push(stats::ReloadRecord {
+            started_at,
+            copy_duration,
+            load_duration,
+            total_duration,
+        });
+
+        self.symbol_cache
+            .get_mut()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+        if loaded_path != self.lib_path {
+            self.temp_copies.push(loaded_path.clone());
+        }
+        self.loaded_path = loaded_path;
+        // the build artifact is considered loaded, even if the copy is modified by the
+        // `before_copy` hook or restored from a snapshot
+        self.lib_timestamp = self.build_artifact_modified()?;
+        if self.lib_timestamp.is_none() {
+            self.lib_content_hash = Some(change::content_hash(&self.lib_path)?);
+        }
+        #[cfg(feature = "checksum")]
+        if self.change_detection == ChangeDetection::Sha256Hash {
+            self.lib_hash = Some(change::sha256_file(&self.lib_path)?);
+        }
+        self.reload_count += 1;
+        self.last_reloaded_at = Some(SystemTime::now());
+
+        if let Err(err) = self.append_lock_record() {
+            log::warn!("failed to write the lock file: {}", error::ErrorChain(&err));
+        }
+
+        self.hooks.run_after();
 
         Ok(())
     }
 }
 
+impl HotCrate {
+    /// Fails if [`SymbolGuard`]s or [`PinnedHotCrate`]s block reloading
+    fn check_unlocked(&self) -> Result<()> {
+        if Arc::strong_count(&self.guards) > 1 {
+            return Err(HotCrateError::Busy);
+        }
+
+        if self.is_pinned() {
+            return Err(HotCrateError::Pinned);
+        }
+
+        Ok(())
+    }
+}
+
+/// Nothing to hold without the `tracing` feature. See [`HotCrate::reload_span`].
+#[cfg(not(feature = "tracing"))]
+struct ReloadSpan;
+
+/// Temporary copy of the build artifact opened by [`HotCrate::force_reload`]
+struct OpenedCopy {
+    lib: Library,
+    path: Utf8PathBuf,
+    copy_duration: Duration,
+    load_duration: Duration,
+}
+
+/// Removes a temporary copy that failed to be loaded
+fn remove_copy(path: &Utf8Path) {
+    if let Err(err) = fs::remove_file(path) {
+        debug_event!("failed to remove `{}`: {}", path, err);
+    }
+}
+
+/// Runs `install_name_tool` or `codesign`, mapping failures to
+/// [`HotCrateError::InstallNameToolFailed`]
+fn run_macos_tool(cmd: &mut std::process::Command) -> Result<()> {
+    let tool = cmd.get_program().to_string_lossy().into_owned();
+
+    let output = cmd.output().map_err(|err| {
+        HotCrateError::InstallNameToolFailed(if err.kind() == std::io::ErrorKind::NotFound {
+            if !tool.contains(std::path::MAIN_SEPARATOR) {
+                format!("`{}` is not found on PATH", tool)
+            } else {
+                format!("`{}` is not found", tool)
+            }
+        } else {
+            format!("`{}` failed to start: {}", tool, err)
+        })
+    })?;
+
+    if !output.status.success() {
+        return Err(HotCrateError::InstallNameToolFailed(format!(
+            "`{}` exited with {}: {}",
+            tool,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Finds the package by the manifest path, or by the package name if the path doesn't match (e.g.
+/// the package replaces a dependency with `[patch]`)
 fn find_dylib_pkg<'a>(main_metadata: &'a Metadata, dylib_toml: &Utf8Path) -> Result<&'a Package> {
-    let dylib_toml = dylib_toml.canonicalize()?;
+    let canonical_toml = dylib_toml.canonicalize()?;
 
-    let dylib_pkg = main_metadata
+    if let Some(pkg) = main_metadata
         .packages
         .iter()
-        .find(|pkg| pkg.manifest_path == dylib_toml)
-        .ok_or_else(|| format!("Unable to find dylib package"))?;
+        .find(|pkg| pkg.manifest_path == canonical_toml)
+    {
+        return Ok(pkg);
+    }
+
+    let name = self::package_name_of(dylib_toml, &canonical_toml)
+        .ok_or_else(|| HotCrateError::ManifestNotFound(dylib_toml.to_path_buf()))?;
+
+    let mut candidates = main_metadata.packages.iter().filter(|pkg| pkg.name == name);
+    let pkg = candidates
+        .next()
+        .ok_or_else(|| HotCrateError::ManifestNotFound(dylib_toml.to_path_buf()))?;
+
+    if candidates.next().is_some() {
+        log::warn!(
+            "multiple packages are named `{}`; `{}` is ambiguous and `{}` is picked",
+            name,
+            dylib_toml,
+            pkg.manifest_path
+        );
+    } else {
+        debug_event!("found `{}` by name at `{}`", dylib_toml, pkg.manifest_path);
+    }
 
-    Ok(dylib_pkg)
+    Ok(pkg)
 }
 
-fn find_dylib_target<'a>(main_metadata: &'a Metadata, dylib_toml: &Utf8Path) -> Result<&'a Target> {
+/// Name of the package at the manifest, running `cargo metadata --no-deps` on it
+fn package_name_of(dylib_toml: &Utf8Path, canonical_toml: &std::path::Path) -> Option<String> {
+    let metadata = MetadataCommand::new()
+        .manifest_path(dylib_toml)
+        .no_deps()
+        .exec()
+        .ok()?;
+
+    metadata
+        .packages
+        .into_iter()
+        .find(|pkg| pkg.manifest_path == canonical_toml)
+        .map(|pkg| pkg.name)
+}
+
+/// Targets of the package with crate type `dylib`, followed by the ones with `cdylib`
+fn find_all_dylib_targets<'a>(
+    main_metadata: &'a Metadata,
+    dylib_toml: &Utf8Path,
+) -> Result<Vec<&'a Target>> {
     let dylib_pkg = self::find_dylib_pkg(main_metadata, dylib_toml)?;
+    let targets = self::dylib_targets(dylib_pkg);
 
-    let target = dylib_pkg
-        .targets
-        .iter()
-        // TODO: allow `cdylib`?
-        .find(|target| target.crate_types.iter().any(|t| t == "dylib"))
-        .ok_or_else(|| format!("Unable to find `dylib` target from {}", dylib_toml))?;
+    if targets.is_empty() {
+        return Err(HotCrateError::DylibTargetMissing(dylib_toml.to_path_buf()));
+    }
 
-    Ok(target)
+    Ok(targets)
 }
 
-fn find_dylib_path(main_metadata: &Metadata, dylib_toml: &Utf8Path) -> Result<Utf8PathBuf> {
-    let target = self::find_dylib_target(main_metadata, dylib_toml)?;
+/// See [`find_all_dylib_targets`]
+fn dylib_targets(pkg: &Package) -> Vec<&Target> {
+    let has_type = |target: &Target, ty: &str| target.crate_types.iter().any(|t| t == ty);
 
-    let debug_or_release = if cfg!(debug_assertions) {
-        "debug"
-    } else {
-        "release"
+    pkg.targets
+        .iter()
+        .filter(|target| has_type(target, "dylib"))
+        .chain(
+            pkg.targets
+                .iter()
+                .filter(|target| !has_type(target, "dylib") && has_type(target, "cdylib")),
+        )
+        .collect()
+}
+
+/// The target with the name, or the first one of [`find_all_dylib_targets`]
+fn find_dylib_target<'a>(
+    main_metadata: &'a Metadata,
+    dylib_toml: &Utf8Path,
+    target_name: Option<&str>,
+) -> Result<&'a Target> {
+    let targets = self::find_all_dylib_targets(main_metadata, dylib_toml)?;
+
+    let target = match target_name {
+        Some(name) => targets.into_iter().find(|target| target.name == name),
+        None => targets.into_iter().next(),
     };
 
-    Ok(main_metadata.target_directory.join(format!(
-        "{}/lib{}.{}",
-        debug_or_release, target.name, DYLIB_EXTENSION
-    )))
+    target.ok_or_else(|| HotCrateError::DylibTargetMissing(dylib_toml.to_path_buf()))
+}
+
+/// `<target dir>/[<triple>/]<profile dir>/<dylib file>`
+fn find_dylib_path(
+    main_metadata: &Metadata,
+    target: &Target,
+    profile: Option<&str>,
+    target_triple: Option<&str>,
+) -> Result<Utf8PathBuf> {
+    let mut dir = main_metadata.target_directory.clone();
+    if let Some(triple) = target_triple {
+        dir.push(triple);
+    }
+
+    Ok(dir
+        .join(self::profile_dir(profile))
+        .join(self::dylib_file_name(target)))
+}
+
+/// The explicit target triple, or `CARGO_BUILD_TARGET`
+fn target_triple(explicit: Option<String>) -> Option<String> {
+    explicit.or_else(|| {
+        std::env::var("CARGO_BUILD_TARGET")
+            .ok()
+            .filter(|s| !s.is_empty())
+    })
+}
+
+/// Maps a cargo profile name to the output directory name under the target directory
+///
+/// Without a profile, `"debug"` or `"release"` are inferred from `debug_assertions` of the host.
+fn profile_dir(profile: Option<&str>) -> &str {
+    match profile {
+        // built-in profiles
+        Some("dev") | Some("test") => "debug",
+        Some("bench") => "release",
+        // `release`, `debug` or custom profiles
+        Some(profile) => profile,
+        None if cfg!(debug_assertions) => "debug",
+        None => "release",
+    }
+}
+
+/// `lib<name>.so`, `lib<name>.dylib` or `<name>.dll`. Both `dylib` and `cdylib` follow this
+/// convention.
+fn dylib_file_name(target: &Target) -> String {
+    // cargo replaces hyphens in library names
+    format!(
+        "{}{}.{}",
+        DYLIB_PREFIX,
+        target.name.replace('-', "_"),
+        DYLIB_EXTENSION
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_dirs() {
+        assert_eq!(profile_dir(Some("dev")), "debug");
+        assert_eq!(profile_dir(Some("test")), "debug");
+        assert_eq!(profile_dir(Some("debug")), "debug");
+        assert_eq!(profile_dir(Some("release")), "release");
+        assert_eq!(profile_dir(Some("bench")), "release");
+        assert_eq!(profile_dir(Some("profiling")), "profiling");
+
+        let default = if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        };
+        assert_eq!(profile_dir(None), default);
+    }
+
+    fn target(name: &str, crate_type: &str) -> Target {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "kind": [crate_type],
+            "crate_types": [crate_type],
+            "src_path": "/path/to/src/lib.rs",
+            "edition": "2018",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn dylib_file_names() {
+        let expected = if cfg!(target_os = "windows") {
+            "my_plugin.dll"
+        } else if cfg!(target_os = "macos") {
+            "libmy_plugin.dylib"
+        } else {
+            "libmy_plugin.so"
+        };
+
+        assert_eq!(dylib_file_name(&target("my-plugin", "dylib")), expected);
+        assert_eq!(dylib_file_name(&target("my_plugin", "cdylib")), expected);
+    }
 }
@@ -0,0 +1,50 @@
+//! `hot_crate.lock`: newline-delimited JSON records of loaded dylibs
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{HotCrate, Result, Utf8Path};
+
+impl HotCrate {
+    /// Appends a record of the currently loaded file to the lock file, if enabled
+    pub(crate) fn append_lock_record(&self) -> Result<()> {
+        let path = match self.lock_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+
+        let timestamp = self
+            .last_reloaded_at
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+
+        let record = serde_json::json!({
+            "timestamp": timestamp,
+            "dylib": self.target_name,
+            "sha256": self::sha256_hex(&self.loaded_path)?,
+            "temp_path": self.loaded_path,
+        });
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", record)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "checksum")]
+fn sha256_hex(path: &Utf8Path) -> Result<Option<String>> {
+    let hash = crate::change::sha256_file(path)?;
+    Ok(Some(hash.iter().map(|b| format!("{:02x}", b)).collect()))
+}
+
+/// `null` without the `checksum` feature
+#[cfg(not(feature = "checksum"))]
+fn sha256_hex(_path: &Utf8Path) -> Result<Option<String>> {
+    Ok(None)
+}
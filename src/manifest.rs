@@ -0,0 +1,217 @@
+//! Patching `Cargo.toml` to build `dylib`s (`manifest` feature)
+
+use std::fs;
+
+use toml_edit::{Array, DocumentMut, Item, Table};
+
+use crate::{Result, Utf8Path};
+
+/// Key under `[package.metadata.hot_crate]` recording the `crate-type` before patching. Empty if
+/// `crate-type` was not specified.
+const ORIGINAL_KEY: &str = "original-crate-type";
+
+/// Adds `"dylib"` to `lib.crate-type` of the manifest so that it can be hot-reloaded
+///
+/// The original `crate-type` (`["rlib"]` if unspecified) is kept, and recorded in
+/// `[package.metadata.hot_crate]` for [`revert_manifest_patch`]. Does nothing if the crate type
+/// already contains `"dylib"`. Warns if `"cdylib"` is already present, which is loadable without
+/// the patch.
+pub fn patch_manifest_for_hot_reload(toml_path: &Utf8Path) -> Result<()> {
+    let mut doc = self::read_manifest(toml_path)?;
+
+    let original = doc
+        .get("lib")
+        .and_then(|lib| lib.get("crate-type"))
+        .and_then(Item::as_array)
+        .cloned();
+
+    let mut crate_types = original.clone().unwrap_or_else(|| {
+        let mut default = Array::new();
+        default.push("rlib");
+        default
+    });
+
+    if crate_types.iter().any(|t| t.as_str() == Some("dylib")) {
+        return Ok(());
+    }
+
+    if crate_types.iter().any(|t| t.as_str() == Some("cdylib")) {
+        log::warn!(
+            "`{}` already has crate type `cdylib`, which is loadable without the patch",
+            toml_path
+        );
+    }
+
+    crate_types.push("dylib");
+
+    let lib = doc
+        .entry("lib")
+        .or_insert_with(|| Item::Table(Table::new()));
+    lib["crate-type"] = toml_edit::value(crate_types);
+
+    let marker = self::marker_table(&mut doc);
+    marker[ORIGINAL_KEY] = toml_edit::value(original.unwrap_or_default());
+
+    fs::write(toml_path, doc.to_string())?;
+    Ok(())
+}
+
+/// Restores `lib.crate-type` changed by [`patch_manifest_for_hot_reload`]. Does nothing if the
+/// manifest is not patched.
+pub fn revert_manifest_patch(toml_path: &Utf8Path) -> Result<()> {
+    let mut doc = self::read_manifest(toml_path)?;
+
+    let original = match doc
+        .get("package")
+        .and_then(|p| p.get("metadata"))
+        .and_then(|m| m.get("hot_crate"))
+        .and_then(|h| h.get(ORIGINAL_KEY))
+        .and_then(Item::as_array)
+    {
+        Some(original) => original.clone(),
+        None => return Ok(()),
+    };
+
+    if let Some(lib) = doc.get_mut("lib").and_then(Item::as_table_like_mut) {
+        if original.is_empty() {
+            lib.remove("crate-type");
+        } else {
+            lib.insert("crate-type", toml_edit::value(original));
+        }
+
+        if lib.is_empty() {
+            doc.remove("lib");
+        }
+    }
+
+    // remove `[package.metadata.hot_crate]`, and `[package.metadata]` if it becomes empty
+    if let Some(metadata) = doc["package"]
+        .get_mut("metadata")
+        .and_then(Item::as_table_like_mut)
+    {
+        metadata.remove("hot_crate");
+        if metadata.is_empty() {
+            doc["package"]
+                .as_table_like_mut()
+                .unwrap()
+                .remove("metadata");
+        }
+    }
+
+    fs::write(toml_path, doc.to_string())?;
+    Ok(())
+}
+
+fn read_manifest(toml_path: &Utf8Path) -> Result<DocumentMut> {
+    Ok(fs::read_to_string(toml_path)?.parse::<DocumentMut>()?)
+}
+
+/// `[package.metadata.hot_crate]`, created if missing
+fn marker_table(doc: &mut DocumentMut) -> &mut Item {
+    let package = doc
+        .entry("package")
+        .or_insert_with(|| Item::Table(Table::new()));
+    let metadata = package
+        .as_table_like_mut()
+        .unwrap()
+        .entry("metadata")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        });
+
+    let metadata = metadata.as_table_like_mut().unwrap();
+    metadata
+        .entry("hot_crate")
+        .or_insert_with(|| Item::Table(Table::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Utf8PathBuf;
+
+    const PACKAGE: &str = r#"[package]
+name = "plugin"
+version = "0.1.0"
+"#;
+
+    /// Patches the manifest and returns it, then checks that reverting restores the original
+    fn round_trip(test: &str, manifest: &str) -> String {
+        let dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+            .unwrap()
+            .join(format!(
+                "hot_crate-test-{}-{}",
+                test,
+                uuid::Uuid::new_v4().simple()
+            ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Cargo.toml");
+        fs::write(&path, manifest).unwrap();
+
+        patch_manifest_for_hot_reload(&path).unwrap();
+        let patched = fs::read_to_string(&path).unwrap();
+
+        revert_manifest_patch(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), manifest);
+
+        fs::remove_dir_all(dir).unwrap();
+        patched
+    }
+
+    fn crate_types(manifest: &str) -> Vec<String> {
+        let doc = manifest.parse::<DocumentMut>().unwrap();
+        doc["lib"]["crate-type"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t.as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn without_crate_type() {
+        let patched = round_trip("none", PACKAGE);
+        assert_eq!(crate_types(&patched), ["rlib", "dylib"]);
+    }
+
+    #[test]
+    fn with_cdylib() {
+        let manifest = format!("{}\n[lib]\ncrate-type = [\"cdylib\"]\n", PACKAGE);
+        let patched = round_trip("cdylib", &manifest);
+        assert_eq!(crate_types(&patched), ["cdylib", "dylib"]);
+    }
+
+    #[test]
+    fn already_dylib() {
+        let manifest = format!("{}\n[lib]\ncrate-type = [\"dylib\"]\n", PACKAGE);
+        let patched = round_trip("dylib", &manifest);
+        assert_eq!(patched, manifest);
+    }
+
+    #[test]
+    fn with_package_metadata() {
+        let manifest = format!(
+            "{}\n[package.metadata.docs]\nall-features = true\n",
+            PACKAGE
+        );
+        let patched = round_trip("metadata", &manifest);
+        assert_eq!(crate_types(&patched), ["rlib", "dylib"]);
+
+        let doc = patched.parse::<DocumentMut>().unwrap();
+        assert!(doc["package"]["metadata"]["docs"]["all-features"]
+            .as_bool()
+            .unwrap());
+    }
+
+    #[test]
+    fn with_package_metadata_table() {
+        let manifest = format!("{}\n[package.metadata]\nkey = \"value\"\n", PACKAGE);
+        let patched = round_trip("metadata-table", &manifest);
+
+        let doc = patched.parse::<DocumentMut>().unwrap();
+        assert_eq!(doc["package"]["metadata"]["key"].as_str(), Some("value"));
+        assert!(doc["package"]["metadata"]["hot_crate"].is_table());
+    }
+}
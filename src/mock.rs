@@ -0,0 +1,83 @@
+//! In-process stand-in of [`HotCrate`](crate::HotCrate) for tests
+
+use std::collections::HashMap;
+
+use crate::{HotCrateError, HotCrateExt, Result};
+
+/// [`HotCrateExt`] backed by a registry of in-process functions
+///
+/// ```
+/// use hot_crate::{HotCrateExt, MockHotCrate};
+///
+/// extern "C" fn answer() -> i32 {
+///     42
+/// }
+///
+/// let mut mock = MockHotCrate::new();
+/// mock.insert(b"answer", answer as *const ());
+///
+/// let f = unsafe { mock.get::<extern "C" fn() -> i32>(b"answer").unwrap() };
+/// assert_eq!(f(), 42);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockHotCrate {
+    symbols: HashMap<Vec<u8>, *const ()>,
+    /// Whether the next [`HotCrateExt::try_reload`] returns true
+    modified: bool,
+    reload_count: usize,
+}
+
+// The registered pointers are only handed back by (unsafe) `get`, whose caller is responsible
+// for their validity, like symbols of `HotCrate`
+unsafe impl Send for MockHotCrate {}
+unsafe impl Sync for MockHotCrate {}
+
+impl MockHotCrate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a symbol. Returns the old pointer if the name is already taken.
+    pub fn insert(&mut self, symbol: &[u8], ptr: *const ()) -> Option<*const ()> {
+        self.symbols.insert(symbol.to_vec(), ptr)
+    }
+
+    pub fn remove(&mut self, symbol: &[u8]) -> Option<*const ()> {
+        self.symbols.remove(symbol)
+    }
+
+    /// Makes the next [`HotCrateExt::try_reload`] return true, as if the dylib is rebuilt
+    pub fn mark_modified(&mut self) {
+        self.modified = true;
+    }
+
+    /// Number of successful [`HotCrateExt::try_reload`] calls
+    pub fn reload_count(&self) -> usize {
+        self.reload_count
+    }
+}
+
+impl HotCrateExt for MockHotCrate {
+    unsafe fn get<T: Copy>(&self, symbol: &[u8]) -> Result<T> {
+        assert_eq!(
+            std::mem::size_of::<T>(),
+            std::mem::size_of::<*const ()>(),
+            "symbols must be pointer-sized"
+        );
+
+        let ptr = self.symbols.get(symbol).ok_or_else(|| {
+            HotCrateError::SymbolNotFound(String::from_utf8_lossy(symbol).into_owned())
+        })?;
+
+        Ok(std::mem::transmute_copy(ptr))
+    }
+
+    fn try_reload(&mut self) -> Result<bool> {
+        if !std::mem::take(&mut self.modified) {
+            return Ok(false);
+        }
+
+        self.reload_count += 1;
+        Ok(true)
+    }
+}
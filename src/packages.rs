@@ -0,0 +1,63 @@
+//! Listing hot-reloadable packages of a workspace
+
+use cargo_metadata::MetadataCommand;
+
+use crate::{HotCrate, Result, Utf8Path, Utf8PathBuf};
+
+/// Package with a `dylib` or `cdylib` target. Listed with [`HotCrate::list_dylib_packages`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DylibPackageInfo {
+    pub package_name: String,
+    pub manifest_path: Utf8PathBuf,
+    /// The first `dylib` target, or the first `cdylib` target (any of them for
+    /// [`find_all_dylib_paths`])
+    pub target_name: String,
+    /// Build artifact path with the profile of the host (and `CARGO_BUILD_TARGET` if set)
+    pub dylib_path: Utf8PathBuf,
+}
+
+/// Dylib target found by [`find_all_dylib_paths`]
+pub type DylibEntry = DylibPackageInfo;
+
+impl HotCrate {
+    /// Lists all the local packages (workspace members and path dependencies) with a `dylib` or
+    /// `cdylib` target, running `cargo metadata` once
+    pub fn list_dylib_packages(main_toml: &Utf8Path) -> Result<Vec<DylibPackageInfo>> {
+        self::list_dylib_targets(main_toml, true)
+    }
+}
+
+/// Lists every `dylib` and `cdylib` target of the local packages (workspace members and path
+/// dependencies), running `cargo metadata` once
+///
+/// Unlike [`HotCrate::list_dylib_packages`], packages with multiple dylib targets make multiple
+/// entries. Each entry can be loaded with [`HotCrate::builder`] (see
+/// [`HotCrateBuilder::target_name`](crate::HotCrateBuilder::target_name)) into a
+/// [`HotCratePool`](crate::HotCratePool).
+pub fn find_all_dylib_paths(main_toml: &Utf8Path) -> Result<Vec<DylibEntry>> {
+    self::list_dylib_targets(main_toml, false)
+}
+
+fn list_dylib_targets(main_toml: &Utf8Path, first_only: bool) -> Result<Vec<DylibPackageInfo>> {
+    let metadata = MetadataCommand::new().manifest_path(main_toml).exec()?;
+
+    let triple = crate::target_triple(None);
+
+    let mut infos = Vec::new();
+    // packages from registries or git repositories have `source`
+    for pkg in metadata.packages.iter().filter(|pkg| pkg.source.is_none()) {
+        let targets = crate::dylib_targets(pkg);
+        let n_targets = if first_only { 1 } else { targets.len() };
+
+        for target in targets.into_iter().take(n_targets) {
+            infos.push(DylibPackageInfo {
+                package_name: pkg.name.clone(),
+                manifest_path: pkg.manifest_path.clone(),
+                target_name: target.name.clone(),
+                dylib_path: crate::find_dylib_path(&metadata, target, None, triple.as_deref())?,
+            });
+        }
+    }
+
+    Ok(infos)
+}
@@ -0,0 +1,30 @@
+//! Blocking reloads while a critical section runs
+
+use std::sync::Arc;
+
+use crate::HotCrate;
+
+/// Pin on a [`HotCrate`] created with [`HotCrate::pin`]. Reloading fails with
+/// [`HotCrateError::Pinned`](crate::HotCrateError::Pinned) until it's dropped.
+///
+/// Like [`SymbolGuard`](crate::SymbolGuard), it does not borrow the [`HotCrate`], so it can be
+/// held while the [`HotCrate`] is reached mutably elsewhere, such as by a reload loop.
+#[derive(Debug)]
+pub struct PinnedHotCrate {
+    _pin: Arc<()>,
+}
+
+impl HotCrate {
+    /// Prevents reloading while the returned pin is alive, such as while rendering a frame or
+    /// processing a network packet
+    pub fn pin(&self) -> PinnedHotCrate {
+        PinnedHotCrate {
+            _pin: Arc::clone(&self.pins),
+        }
+    }
+
+    /// Whether any [`PinnedHotCrate`] is alive
+    pub fn is_pinned(&self) -> bool {
+        Arc::strong_count(&self.pins) > 1
+    }
+}
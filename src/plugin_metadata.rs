@@ -0,0 +1,156 @@
+//! `[package.metadata.hot_crate]` of the dylib package
+
+use std::convert::TryFrom;
+
+use cargo_metadata::MetadataCommand;
+use serde_json::Value;
+
+use crate::{HotCrate, HotCrateError, Result, Utf8Path, Utf8PathBuf};
+
+/// Plugin configuration in `[package.metadata.hot_crate]` of the dylib's `Cargo.toml`. Read with
+/// [`HotCrate::read_plugin_metadata`].
+///
+/// ```toml
+/// [package.metadata.hot_crate]
+/// api_version = 2
+/// auto_reload_enabled = true
+/// temp_dir = "/custom/tmp"
+/// ```
+///
+/// Missing keys are `None`. Unknown keys are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PluginMetadata {
+    /// Can be checked with [`VersionedHotCrate`](crate::VersionedHotCrate)
+    pub api_version: Option<u32>,
+    pub auto_reload_enabled: Option<bool>,
+    /// Can be given to [`HotCrateBuilder::temp_dir`](crate::HotCrateBuilder::temp_dir)
+    pub temp_dir: Option<Utf8PathBuf>,
+}
+
+impl HotCrate {
+    /// Reads `[package.metadata.hot_crate]` of the dylib package, running `cargo metadata` on
+    /// it. Fails with [`HotCrateError::InvalidPluginMetadata`] if a known key has a wrong type.
+    pub fn read_plugin_metadata(dylib_toml: &Utf8Path) -> Result<PluginMetadata> {
+        let metadata = MetadataCommand::new()
+            .manifest_path(dylib_toml)
+            .no_deps()
+            .exec()?;
+        let pkg = crate::find_dylib_pkg(&metadata, dylib_toml)?;
+
+        self::parse(&pkg.metadata)
+    }
+}
+
+/// Parses `package.metadata` of the dylib package
+fn parse(metadata: &Value) -> Result<PluginMetadata> {
+    let table = match metadata.get("hot_crate") {
+        Some(Value::Object(table)) => table,
+        Some(_) => {
+            return Err(HotCrateError::InvalidPluginMetadata(
+                "hot_crate".to_string(),
+            ))
+        }
+        None => return Ok(PluginMetadata::default()),
+    };
+
+    let invalid = |key: &str| HotCrateError::InvalidPluginMetadata(format!("hot_crate.{}", key));
+
+    let api_version = match table.get("api_version") {
+        Some(value) => Some(
+            value
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| invalid("api_version"))?,
+        ),
+        None => None,
+    };
+
+    let auto_reload_enabled = match table.get("auto_reload_enabled") {
+        Some(value) => Some(
+            value
+                .as_bool()
+                .ok_or_else(|| invalid("auto_reload_enabled"))?,
+        ),
+        None => None,
+    };
+
+    let temp_dir = match table.get("temp_dir") {
+        Some(value) => Some(Utf8PathBuf::from(
+            value.as_str().ok_or_else(|| invalid("temp_dir"))?,
+        )),
+        None => None,
+    };
+
+    Ok(PluginMetadata {
+        api_version,
+        auto_reload_enabled,
+        temp_dir,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn invalid_key(metadata: Value) -> String {
+        match parse(&metadata) {
+            Err(HotCrateError::InvalidPluginMetadata(key)) => key,
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_known_keys() {
+        let metadata = json!({
+            "hot_crate": {
+                "api_version": 2,
+                "auto_reload_enabled": true,
+                "temp_dir": "/custom/tmp",
+                "unknown": "ignored",
+            },
+            "docs": {},
+        });
+
+        assert_eq!(
+            parse(&metadata).unwrap(),
+            PluginMetadata {
+                api_version: Some(2),
+                auto_reload_enabled: Some(true),
+                temp_dir: Some(Utf8PathBuf::from("/custom/tmp")),
+            }
+        );
+    }
+
+    #[test]
+    fn missing_keys_are_none() {
+        assert_eq!(parse(&Value::Null).unwrap(), PluginMetadata::default());
+        assert_eq!(
+            parse(&json!({ "hot_crate": {} })).unwrap(),
+            PluginMetadata::default()
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_types() {
+        assert_eq!(invalid_key(json!({ "hot_crate": 1 })), "hot_crate");
+        assert_eq!(
+            invalid_key(json!({ "hot_crate": { "api_version": "2" } })),
+            "hot_crate.api_version"
+        );
+        assert_eq!(
+            invalid_key(json!({ "hot_crate": { "api_version": 1u64 << 32 } })),
+            "hot_crate.api_version"
+        );
+        assert_eq!(
+            invalid_key(json!({ "hot_crate": { "auto_reload_enabled": 1 } })),
+            "hot_crate.auto_reload_enabled"
+        );
+        assert_eq!(
+            invalid_key(json!({ "hot_crate": { "temp_dir": false } })),
+            "hot_crate.temp_dir"
+        );
+    }
+}
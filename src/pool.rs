@@ -0,0 +1,83 @@
+//! Collection of named [`HotCrate`]s
+
+use crate::{HotCrate, Result};
+
+/// Named [`HotCrate`]s, reloaded in insertion order
+#[derive(Debug, Default)]
+pub struct HotCratePool {
+    crates: Vec<(String, HotCrate)>,
+}
+
+impl HotCratePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.crates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.crates.is_empty()
+    }
+
+    /// Adds a library. Returns the old library if the name is already taken.
+    pub fn insert(&mut self, name: impl Into<String>, hot: HotCrate) -> Option<HotCrate> {
+        let name = name.into();
+
+        match self.crates.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, old)) => Some(std::mem::replace(old, hot)),
+            None => {
+                self.crates.push((name, hot));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<HotCrate> {
+        let i = self.crates.iter().position(|(n, _)| n == name)?;
+        Some(self.crates.remove(i).1)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HotCrate> {
+        self.crates.iter().find(|(n, _)| n == name).map(|(_, h)| h)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut HotCrate> {
+        self.crates
+            .iter_mut()
+            .find(|(n, _)| n == name)
+            .map(|(_, h)| h)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &HotCrate)> {
+        self.crates.iter().map(|(n, h)| (n.as_str(), h))
+    }
+
+    /// Calls [`HotCrate::try_reload`] on each library. Returns which libraries are reloaded.
+    ///
+    /// Stops at the first error.
+    #[cfg(not(feature = "parallel-reload"))]
+    pub fn try_reload_all(&mut self) -> Result<Vec<(String, bool)>> {
+        self.crates
+            .iter_mut()
+            .map(|(name, hot)| Ok((name.clone(), hot.try_reload()?.was_reloaded())))
+            .collect()
+    }
+
+    /// Calls [`HotCrate::try_reload`] on each library in parallel with `rayon`. Returns which
+    /// libraries are reloaded, in insertion order.
+    ///
+    /// Returns an error if any library fails; other libraries may be reloaded or not. Opening
+    /// libraries from multiple threads is fine since [`Library::new`](libloading::Library::new)
+    /// is thread-safe on the supported platforms.
+    #[cfg(feature = "parallel-reload")]
+    pub fn try_reload_all(&mut self) -> Result<Vec<(String, bool)>> {
+        use rayon::prelude::*;
+
+        self.crates
+            .par_iter_mut()
+            .map(|(name, hot)| Ok((name.clone(), hot.try_reload()?.was_reloaded())))
+            .collect()
+    }
+}
@@ -0,0 +1,166 @@
+//! Rebuilding the dylib with `cargo build`
+
+use std::{
+    collections::HashMap,
+    process::{Command, Output},
+    thread::JoinHandle,
+};
+
+use crate::{HotCrate, HotCrateError, Result};
+
+/// Options of `cargo build` run by [`HotCrate::try_rebuild_and_reload_with`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildOptions {
+    /// `--features`
+    pub features: Vec<String>,
+    /// `--no-default-features`
+    pub no_default_features: bool,
+    /// Environment variables such as `RUSTFLAGS`
+    pub extra_env: HashMap<String, String>,
+    /// `--offline` if [`NetworkAccess::Offline`]
+    pub network: NetworkAccess,
+}
+
+/// Whether `cargo build` may access the network. See [`HotCrateBuilder::network`].
+///
+/// [`HotCrateBuilder::network`]: crate::HotCrateBuilder::network
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NetworkAccess {
+    #[default]
+    Online,
+    /// Runs `cargo build --offline`
+    Offline,
+}
+
+impl HotCrate {
+    /// Runs `cargo build -p <dylib package>` and then reloads the dylib if it's changed. Returns
+    /// true if the dylib is reloaded.
+    ///
+    /// Unlike [`HotCrate::try_reload`], the [`HotCrateBuilder::debounce`] is not applied, since
+    /// the build artifact is completely written when `cargo build` finishes.
+    ///
+    /// Blocks until `cargo build` finishes. If the build fails, [`HotCrateError::BuildFailed`] is
+    /// returned and the currently loaded library is kept. Fails with
+    /// [`HotCrateError::NoMetadata`] if the dylib is loaded with [`HotCrate::load_path`].
+    ///
+    /// `cargo build` runs with the options given to [`HotCrateBuilder::build_options`].
+    ///
+    /// [`HotCrateBuilder::build_options`]: crate::HotCrateBuilder::build_options
+    /// [`HotCrateBuilder::debounce`]: crate::HotCrateBuilder::debounce
+    pub fn try_rebuild_and_reload(&mut self) -> Result<bool> {
+        let options = self.build_options.clone();
+        self.try_rebuild_and_reload_with(&options)
+    }
+
+    /// [`HotCrate::try_rebuild_and_reload`] with custom `cargo build` options
+    pub fn try_rebuild_and_reload_with(&mut self, options: &BuildOptions) -> Result<bool> {
+        let output = self.cargo_build_command(options)?.output()?;
+        self::check_build_output(&output)?;
+
+        self.reload_built()
+    }
+
+    /// Reloads the dylib if the finished build changed it. The debounce is skipped since the
+    /// artifact is completely written.
+    pub(crate) fn reload_built(&mut self) -> Result<bool> {
+        let timestamp = self.build_artifact_modified()?;
+        if !self.has_changed(timestamp)? {
+            return Ok(false);
+        }
+
+        self.force_reload()?;
+        Ok(true)
+    }
+
+    /// Starts `cargo build` on a background thread and returns immediately
+    ///
+    /// The handle yields [`HotCrateError::BuildFailed`] if the build fails. Call
+    /// [`HotCrate::try_reload`] after the handle finishes (see [`JoinHandle::is_finished`]) to pick
+    /// up the result.
+    pub fn spawn_cargo_build(&self) -> Result<JoinHandle<Result<()>>> {
+        let mut cmd = self.cargo_build_command(&self.build_options)?;
+
+        Ok(std::thread::spawn(move || {
+            let output = cmd.output()?;
+            self::check_build_output(&output)
+        }))
+    }
+
+    /// "Build if needed, then use": reloads the dylib and calls `f` with the up-to-date
+    /// [`HotCrate`]
+    ///
+    /// If the build artifact is already newer than the loaded library, it's reloaded without
+    /// running `cargo build`. Otherwise [`HotCrate::try_rebuild_and_reload`] is run. Getting
+    /// symbols in `f` is still `unsafe`, as with [`HotCrate::get`].
+    ///
+    /// [`HotCrate::get`]: crate::HotCrate::get
+    pub fn with_rebuilt<R>(&mut self, f: impl FnOnce(&HotCrate) -> R) -> Result<R> {
+        if self.has_changed(self.build_artifact_modified()?)? {
+            self.force_reload()?;
+        } else {
+            self.try_rebuild_and_reload()?;
+        }
+
+        Ok(f(self))
+    }
+
+    /// `cargo build` command for the dylib package with the configured profile
+    pub(crate) fn cargo_build_command(&self, options: &BuildOptions) -> Result<Command> {
+        let main_metadata = self
+            .main_metadata
+            .as_ref()
+            .ok_or(HotCrateError::NoMetadata)?;
+
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let mut cmd = Command::new(cargo);
+        cmd.arg("build")
+            .arg("--manifest-path")
+            .arg(main_metadata.workspace_root.join("Cargo.toml"))
+            .arg("-p")
+            .arg(&self.package_name);
+
+        match self.profile.as_deref() {
+            Some("dev") | Some("debug") => {}
+            Some("release") => {
+                cmd.arg("--release");
+            }
+            Some(profile) => {
+                cmd.arg("--profile").arg(profile);
+            }
+            None if cfg!(debug_assertions) => {}
+            None => {
+                cmd.arg("--release");
+            }
+        }
+
+        if let Some(ref triple) = self.target_triple {
+            cmd.arg("--target").arg(triple);
+        }
+
+        if !options.features.is_empty() {
+            cmd.arg("--features").arg(options.features.join(","));
+        }
+
+        if options.no_default_features {
+            cmd.arg("--no-default-features");
+        }
+
+        if options.network == NetworkAccess::Offline {
+            cmd.arg("--offline");
+        }
+
+        cmd.envs(&options.extra_env);
+
+        Ok(cmd)
+    }
+}
+
+pub(crate) fn check_build_output(output: &Output) -> Result<()> {
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(HotCrateError::BuildFailed(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
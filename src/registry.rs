@@ -0,0 +1,133 @@
+//! Shared registry of [`HotCrate`]s by plugin ID
+
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::Deref,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use crate::{HotCrate, Result};
+
+/// [`HotCrate`]s by plugin ID, shared with `&self` across the application
+///
+/// Unlike [`HotCratePool`](crate::HotCratePool), the libraries are behind a lock, so the
+/// registry can be put in a `static` (with `std::sync::OnceLock`) or an `Arc` and plugins can be
+/// accessed without passing `&mut` references through the call stack.
+///
+/// ```no_run
+/// # fn main() -> hot_crate::Result<()> {
+/// use hot_crate::{HotCrate, HotCrateRegistry};
+///
+/// let registry = HotCrateRegistry::new();
+/// registry.register("plugin", HotCrate::load_path("/path/to/libplugin.so")?);
+///
+/// if let Some(plugin) = registry.get("plugin") {
+///     let value = unsafe { plugin.call0::<i32>(b"value")? };
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct HotCrateRegistry {
+    crates: RwLock<HashMap<String, HotCrate>>,
+}
+
+/// [`HotCrate`] borrowed from a [`HotCrateRegistry`]
+///
+/// Holds the read lock of the registry, which blocks [`HotCrateRegistry::register`] and
+/// [`HotCrateRegistry::reload_all`] until dropped.
+pub struct RegistryRef<'a> {
+    guard: RwLockReadGuard<'a, HashMap<String, HotCrate>>,
+    id: String,
+}
+
+impl fmt::Debug for RegistryRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RegistryRef").field(&**self).finish()
+    }
+}
+
+impl Deref for RegistryRef<'_> {
+    type Target = HotCrate;
+
+    fn deref(&self) -> &HotCrate {
+        // the entry can't be removed while the read lock is held
+        &self.guard[&self.id]
+    }
+}
+
+impl HotCrateRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a library. Returns the old library if the ID is already taken.
+    pub fn register(&self, id: impl Into<String>, hot: HotCrate) -> Option<HotCrate> {
+        self.write().insert(id.into(), hot)
+    }
+
+    pub fn unregister(&self, id: &str) -> Option<HotCrate> {
+        self.write().remove(id)
+    }
+
+    /// Borrows a library
+    ///
+    /// A `&HotCrate` can't outlive the lock of the registry, so the returned guard dereferences to
+    /// it instead. Drop the guard before registering or reloading libraries on the same thread, or
+    /// they deadlock.
+    pub fn get(&self, id: &str) -> Option<RegistryRef<'_>> {
+        let guard = self.read();
+        if !guard.contains_key(id) {
+            return None;
+        }
+
+        Some(RegistryRef {
+            guard,
+            id: id.to_string(),
+        })
+    }
+
+    /// Calls `f` with the library under the write lock, such as to reload only one of them
+    pub fn with_mut<R>(&self, id: &str, f: impl FnOnce(&mut HotCrate) -> R) -> Option<R> {
+        self.write().get_mut(id).map(f)
+    }
+
+    /// IDs of the registered libraries, sorted
+    pub fn ids(&self) -> Vec<String> {
+        let mut ids: Vec<_> = self.read().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Calls [`HotCrate::try_reload`] on each library. Returns which libraries are reloaded,
+    /// sorted by ID.
+    ///
+    /// Unlike [`HotCratePool::try_reload_all`](crate::HotCratePool::try_reload_all), a failure
+    /// does not stop reloading the other libraries.
+    pub fn reload_all(&self) -> Vec<(String, Result<bool>)> {
+        let mut results: Vec<_> = self
+            .write()
+            .iter_mut()
+            .map(|(id, hot)| (id.clone(), hot.try_reload().map(|o| o.was_reloaded())))
+            .collect();
+        results.sort_by(|(a, _), (b, _)| a.cmp(b));
+        results
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, HotCrate>> {
+        self.crates.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, HotCrate>> {
+        self.crates.write().unwrap_or_else(|err| err.into_inner())
+    }
+}
@@ -0,0 +1,86 @@
+//! Undoing reloads
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{deps, HotCrate, HotCrateError, Result, Utf8Path, Utf8PathBuf};
+
+/// Loaded version of a [`HotCrate`], created with [`HotCrate::snapshot`] and rolled back to with
+/// [`HotCrate::restore`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    lib_path: Utf8PathBuf,
+    loaded_path: Utf8PathBuf,
+    reload_count: usize,
+}
+
+impl Snapshot {
+    /// The file loaded at the time
+    pub fn loaded_path(&self) -> &Utf8Path {
+        &self.loaded_path
+    }
+
+    pub fn reload_count(&self) -> usize {
+        self.reload_count
+    }
+}
+
+impl HotCrate {
+    /// Remembers the currently loaded version, such as before reloading a dylib that might crash
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            lib_path: self.lib_path.clone(),
+            loaded_path: self.loaded_path.clone(),
+            reload_count: self.reload_count,
+        }
+    }
+
+    /// Loads the version of the snapshot again ("undo reload")
+    ///
+    /// The snapshotted temporary copy is re-opened like a reload, running the reload hooks. Fails
+    /// with [`HotCrateError::SnapshotMissing`] if the copy is removed (see
+    /// [`HotCrateBuilder::max_reload_copies`]), and keeps the current library on failure.
+    ///
+    /// The current build artifact is then considered loaded, so [`HotCrate::try_reload`] waits
+    /// for the next build instead of reloading the version that was undone. The reload count is
+    /// set back to the one of the snapshot.
+    ///
+    /// [`HotCrateBuilder::max_reload_copies`]: crate::HotCrateBuilder::max_reload_copies
+    pub fn restore(&mut self, snap: Snapshot) -> Result<()> {
+        self.check_unlocked()?;
+
+        if !snap.loaded_path.is_file() {
+            return Err(HotCrateError::SnapshotMissing(snap.loaded_path));
+        }
+
+        let old_path = std::mem::replace(&mut self.lib_path, snap.lib_path);
+        if let Err(err) = self.reopen(snap.loaded_path) {
+            self.lib_path = old_path;
+            return Err(err);
+        }
+
+        self.reload_count = snap.reload_count;
+        Ok(())
+    }
+
+    /// Opens the `loaded_path` again in place of the current library
+    fn reopen(&mut self, loaded_path: Utf8PathBuf) -> Result<()> {
+        self.hooks.run_before();
+
+        let start = Instant::now();
+        let started_at = SystemTime::now();
+
+        let new_lib = unsafe { deps::open_library(&loaded_path, &self.lib_path, &self.load_env)? };
+        let load_duration = start.elapsed();
+        self.hooks.validate(&new_lib)?;
+        debug_event!("restoring `{}`", loaded_path);
+
+        self.swap_library(
+            new_lib,
+            loaded_path,
+            started_at,
+            start,
+            Duration::ZERO,
+            load_duration,
+        )
+    }
+}
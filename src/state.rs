@@ -0,0 +1,79 @@
+//! Transferring plugin state across reloads
+
+use std::ffi::c_void;
+
+use libloading::Library;
+
+/// State of a plugin that survives reloading
+///
+/// # ABI
+///
+/// A dylib can export two optional `extern "C"` functions:
+///
+/// - `hot_serialize: `[`HotSerializeFn`] is called on the old library right before it's replaced.
+///   It must call `write` once with `ctx` and its state as bytes. The bytes are copied by
+///   `hot_crate`, so they can be freed after `write` returns.
+/// - `hot_deserialize: `[`HotDeserializeFn`] is called on the new library right after it's loaded,
+///   with the bytes written by the old library. The pointer is valid only during the call.
+///
+/// `hot_deserialize` is not called if the old library does not export `hot_serialize`. The byte
+/// format is up to the plugin; it must be understood by both the old and the new library.
+/// [`write_state`] and [`read_state`] implement the functions with [`StateTransfer`].
+pub trait StateTransfer: 'static {
+    fn serialize(&self) -> Vec<u8>;
+    fn deserialize(data: &[u8]) -> Self;
+}
+
+/// Callback passed to `hot_serialize`
+pub type StateWriter = extern "C" fn(ctx: *mut c_void, data: *const u8, len: usize);
+
+/// Type of the `hot_serialize` symbol
+pub type HotSerializeFn = unsafe extern "C" fn(ctx: *mut c_void, write: StateWriter);
+
+/// Type of the `hot_deserialize` symbol
+pub type HotDeserializeFn = unsafe extern "C" fn(data: *const u8, len: usize);
+
+/// Implements `hot_serialize` in the plugin
+///
+/// # Safety
+///
+/// `ctx` and `write` must be the arguments given to `hot_serialize`.
+pub unsafe fn write_state<T: StateTransfer>(state: &T, ctx: *mut c_void, write: StateWriter) {
+    let bytes = state.serialize();
+    write(ctx, bytes.as_ptr(), bytes.len());
+}
+
+/// Implements `hot_deserialize` in the plugin
+///
+/// # Safety
+///
+/// `data` and `len` must be the arguments given to `hot_deserialize`.
+pub unsafe fn read_state<T: StateTransfer>(data: *const u8, len: usize) -> T {
+    T::deserialize(std::slice::from_raw_parts(data, len))
+}
+
+/// Calls `hot_serialize` if the library exports it
+pub(crate) fn serialize(lib: &Library) -> Option<Vec<u8>> {
+    extern "C" fn write(ctx: *mut c_void, data: *const u8, len: usize) {
+        let buf = unsafe { &mut *(ctx as *mut Vec<u8>) };
+        buf.extend_from_slice(unsafe { std::slice::from_raw_parts(data, len) });
+    }
+
+    unsafe {
+        let f = lib.get::<HotSerializeFn>(b"hot_serialize").ok()?;
+        let mut buf = Vec::new();
+        f(&mut buf as *mut Vec<u8> as *mut c_void, write);
+        debug_event!("serialized {} bytes of state", buf.len());
+        Some(buf)
+    }
+}
+
+/// Calls `hot_deserialize` if the library exports it
+pub(crate) fn deserialize(lib: &Library, data: &[u8]) {
+    unsafe {
+        if let Ok(f) = lib.get::<HotDeserializeFn>(b"hot_deserialize") {
+            f(data.as_ptr(), data.len());
+            debug_event!("deserialized {} bytes of state", data.len());
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Timings of recent reloads for profiling
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime},
+};
+
+use crate::HotCrate;
+
+/// Timings of a [`HotCrate::force_reload`], recorded in [`HotCrate::reload_stats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReloadRecord {
+    pub started_at: SystemTime,
+    /// Placing the build artifact at the temporary path (and patching it on macOS)
+    pub copy_duration: Duration,
+    /// Opening the library (`dlopen` or `LoadLibrary`)
+    pub load_duration: Duration,
+    /// From copying to opening, including state transfer
+    pub total_duration: Duration,
+}
+
+/// Ring buffer of the recent [`ReloadRecord`]s
+#[derive(Debug, Clone)]
+pub(crate) struct ReloadStats {
+    records: VecDeque<ReloadRecord>,
+    capacity: usize,
+}
+
+impl ReloadStats {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, record: ReloadRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+        // so that `HotCrate::reload_stats` can return one slice
+        self.records.make_contiguous();
+    }
+}
+
+impl HotCrate {
+    /// Timings of the recent reloads, oldest first. Up to
+    /// [`HotCrateBuilder::reload_stats_capacity`](crate::HotCrateBuilder::reload_stats_capacity)
+    /// records are kept.
+    pub fn reload_stats(&self) -> &[ReloadRecord] {
+        self.reload_stats.records.as_slices().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(secs: u64) -> ReloadRecord {
+        ReloadRecord {
+            started_at: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            copy_duration: Duration::ZERO,
+            load_duration: Duration::ZERO,
+            total_duration: Duration::ZERO,
+        }
+    }
+
+    fn started(stats: &ReloadStats) -> Vec<u64> {
+        stats
+            .records
+            .iter()
+            .map(|r| {
+                r.started_at
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn push_drops_oldest() {
+        let mut stats = ReloadStats::new(3);
+        for secs in 0..5 {
+            stats.push(record(secs));
+        }
+        assert_eq!(started(&stats), [2, 3, 4]);
+        assert_eq!(stats.records.as_slices().1.len(), 0);
+    }
+
+    #[test]
+    fn zero_capacity_keeps_nothing() {
+        let mut stats = ReloadStats::new(0);
+        stats.push(record(0));
+        assert!(stats.records.is_empty());
+    }
+}
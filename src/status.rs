@@ -0,0 +1,160 @@
+//! Polling the build without blocking on it
+
+use std::{
+    fs, io,
+    time::{Duration, Instant},
+};
+
+use crate::{HotCrate, Result};
+
+/// Result of [`HotCrate::try_reload`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadOutcome {
+    /// The dylib is up to date
+    Unchanged,
+    /// The dylib was reloaded
+    Reloaded {
+        /// [`HotCrate::reload_count`] after reloading
+        reload_count: usize,
+        /// Time spent in [`HotCrate::force_reload`]
+        duration: Duration,
+    },
+}
+
+impl ReloadOutcome {
+    /// Whether the dylib was reloaded, which `try_reload` used to return
+    pub fn was_reloaded(&self) -> bool {
+        matches!(self, Self::Reloaded { .. })
+    }
+}
+
+/// Result of [`HotCrate::try_reload_nonblocking`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReloadStatus {
+    /// The dylib is up to date
+    NotChanged,
+    /// The dylib was reloaded
+    Reloaded,
+    /// Cargo is building the dylib, since the given duration ago (counted from the first poll
+    /// that noticed the build)
+    BuildInProgress(Duration),
+}
+
+impl HotCrate {
+    /// [`HotCrate::try_reload`] that doesn't load the build artifact while cargo is writing it
+    ///
+    /// A build is detected by the lock on `.cargo-lock` in the profile directory, which cargo
+    /// holds while building. Callers can show a "building..." indicator on
+    /// [`ReloadStatus::BuildInProgress`] and poll again later.
+    pub fn try_reload_nonblocking(&mut self) -> Result<ReloadStatus> {
+        if self.is_build_locked()? {
+            let since = *self.build_noticed_at.get_or_insert_with(Instant::now);
+            return Ok(ReloadStatus::BuildInProgress(since.elapsed()));
+        }
+
+        self.build_noticed_at = None;
+
+        Ok(if self.try_reload()?.was_reloaded() {
+            ReloadStatus::Reloaded
+        } else {
+            ReloadStatus::NotChanged
+        })
+    }
+
+    fn is_build_locked(&self) -> Result<bool> {
+        let lock_path = self.lib_path.parent().unwrap().join(".cargo-lock");
+        let file = match fs::File::open(&lock_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+
+        // cargo holds an exclusive lock; the shared lock is released when the file is closed
+        match try_lock_shared(&file) {
+            Ok(()) => Ok(false),
+            Err(err) if is_lock_contended(&err) => Ok(true),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// `flock(2)`, which cargo uses for `.cargo-lock` on Unix
+#[cfg(unix)]
+fn try_lock_shared(file: &fs::File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the file descriptor is open while `file` is borrowed
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(unix)]
+fn is_lock_contended(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock
+}
+
+/// `LockFileEx`, which cargo uses for `.cargo-lock` on Windows
+#[cfg(windows)]
+fn try_lock_shared(file: &fs::File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use winapi::um::{fileapi::LockFileEx, minwinbase::LOCKFILE_FAIL_IMMEDIATELY};
+
+    // SAFETY: the handle is open while `file` is borrowed, and `LockFileEx` doesn't keep the
+    // `OVERLAPPED` of a synchronous handle
+    let locked = unsafe {
+        let mut overlapped = std::mem::zeroed();
+        LockFileEx(
+            file.as_raw_handle() as _,
+            LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            !0,
+            !0,
+            &mut overlapped,
+        )
+    };
+
+    if locked != 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(windows)]
+fn is_lock_contended(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(winapi::shared::winerror::ERROR_LOCK_VIOLATION as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn shared_lock_is_contended_by_exclusive_lock() {
+        use std::os::unix::io::AsRawFd;
+
+        let path = std::env::temp_dir().join(format!(
+            "hot_crate-test-cargo-lock-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        let cargo = fs::File::create(&path).unwrap();
+        let probe = fs::File::open(&path).unwrap();
+
+        try_lock_shared(&probe).unwrap();
+        drop(probe);
+
+        // what cargo does while building
+        assert_eq!(unsafe { libc::flock(cargo.as_raw_fd(), libc::LOCK_EX) }, 0);
+        let err = try_lock_shared(&fs::File::open(&path).unwrap()).unwrap_err();
+        assert!(is_lock_contended(&err));
+
+        drop(cargo);
+        try_lock_shared(&fs::File::open(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
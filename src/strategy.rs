@@ -0,0 +1,153 @@
+//! How the dylib is placed at the temporary path before loading
+
+use std::{fs, path::Path, process::Command, time::Instant};
+
+use crate::{
+    deps, hooks::CopyHook, HotCrate, Result, Utf8Path, Utf8PathBuf, DYLIB_EXTENSION, DYLIB_PREFIX,
+};
+
+/// How [`HotCrate::force_reload`] creates the temporary file to load. Set with
+/// [`HotCrateBuilder::reload_strategy`](crate::HotCrateBuilder::reload_strategy).
+///
+/// The dylib is copied if hard-linking or symlinking fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReloadStrategy {
+    /// Copies the dylib. Works anywhere but takes time and disk space for large dylibs.
+    Copy,
+    /// Hard-links the dylib. Fast and takes no extra disk space, but only works within a single
+    /// filesystem. Cargo replaces the build artifact rather than overwriting it, so the link keeps
+    /// pointing to the old contents. Not recommended on macOS, where `install_name_tool` would
+    /// modify the build artifact through the link, nor on Windows, where the loaded link locks the
+    /// build artifact (the same file) so that the next `cargo build` can't replace it.
+    HardLink,
+    /// Makes the temporary path a symlink to the build artifact, avoiding any copy. The
+    /// `install_name_tool` workaround is skipped (it would modify the build artifact), so macOS
+    /// may return the old library on reload. Creating symlinks can require privileges on Windows.
+    Symlink,
+}
+
+/// Where and how [`HotCrate::force_reload`] (and the initial load) places the build artifact
+pub(crate) struct Placement<'a> {
+    pub temp_dir: &'a Utf8Path,
+    pub package_name: &'a str,
+    pub target_name: &'a str,
+    pub strategy: ReloadStrategy,
+    pub copy_hook: Option<&'a CopyHook>,
+    /// `install_name_tool` run on macOS, or `None` to skip it
+    pub install_name_tool: Option<&'a Path>,
+}
+
+impl Placement<'_> {
+    pub fn tmp_dylib_path(&self) -> Utf8PathBuf {
+        // ${TMP_DIR}/hot_crate/lib${plugin}-${uuid}.${ext}
+        // (unique even if other processes are reloading the same dylib)
+        let tmp = self.temp_dir.join(self.package_name);
+        tmp.join(format!(
+            "{}{}-{}.{}",
+            DYLIB_PREFIX,
+            self.target_name,
+            uuid::Uuid::new_v4().simple(),
+            DYLIB_EXTENSION,
+        ))
+    }
+
+    /// Places the build artifact `lib_path` at a new temporary path, which is returned
+    pub fn place(&self, lib_path: &Utf8Path) -> Result<Utf8PathBuf> {
+        let tmp_dylib_path = self.tmp_dylib_path();
+        let tmp_dir = tmp_dylib_path.parent().unwrap();
+
+        // Copy the dylib to the tmp location.
+        fs::create_dir_all(tmp_dir)?;
+        let copy_start = Instant::now();
+        match self.copy_hook {
+            Some(copy) => (copy.0)(lib_path, &tmp_dylib_path)?,
+            None => self::copy_dylib(self.strategy, lib_path, &tmp_dylib_path)?,
+        }
+        debug_event!(
+            "copied `{}` ({} bytes) to `{}` in {:?}",
+            lib_path,
+            fs::metadata(lib_path).map_or(0, |m| m.len()),
+            tmp_dylib_path,
+            copy_start.elapsed()
+        );
+
+        let install_name_tool = self
+            .install_name_tool
+            .filter(|_| cfg!(target_os = "macos") && self.strategy != ReloadStrategy::Symlink);
+        if let Some(install_name_tool) = install_name_tool {
+            let mut cmd = Command::new(install_name_tool);
+            cmd.current_dir(tmp_dir).arg("-id").arg("''");
+
+            // point `@rpath` dependencies in the target directory to their absolute paths
+            let rpath_deps = deps::rpath_deps(&tmp_dylib_path, lib_path);
+            for (install_name, dep) in &rpath_deps {
+                cmd.arg("-change").arg(install_name).arg(dep);
+            }
+
+            crate::run_macos_tool(cmd.arg(tmp_dylib_path.file_name().unwrap()))?;
+            debug_event!(
+                "ran `install_name_tool` on `{}` changing {} `@rpath` dependencies",
+                tmp_dylib_path,
+                rpath_deps.len()
+            );
+
+            // Apple Silicon refuses to load binaries whose signature is invalidated by
+            // `install_name_tool`, so sign the copy again (ad-hoc)
+            if cfg!(target_arch = "aarch64") {
+                crate::run_macos_tool(
+                    Command::new("codesign")
+                        .current_dir(tmp_dir)
+                        .args(["--sign", "-", "--force"])
+                        .arg(tmp_dylib_path.file_name().unwrap()),
+                )?;
+                debug_event!("ran `codesign` on `{}`", tmp_dylib_path);
+            }
+        }
+
+        Ok(tmp_dylib_path)
+    }
+}
+
+impl HotCrate {
+    pub(crate) fn placement(&self) -> Placement<'_> {
+        Placement {
+            temp_dir: &self.temp_dir,
+            package_name: &self.package_name,
+            target_name: &self.target_name,
+            strategy: self.reload_strategy,
+            copy_hook: self.hooks.copy.as_ref(),
+            install_name_tool: self.install_name_tool.as_deref(),
+        }
+    }
+}
+
+fn copy_dylib(strategy: ReloadStrategy, src: &Utf8Path, dst: &Utf8Path) -> Result<()> {
+    // linking fails if the destination exists
+    if strategy != ReloadStrategy::Copy {
+        let _ = fs::remove_file(dst);
+    }
+
+    let linked = match strategy {
+        ReloadStrategy::Copy => return Ok(fs::copy(src, dst).map(|_| ())?),
+        ReloadStrategy::HardLink => fs::hard_link(src, dst),
+        ReloadStrategy::Symlink => self::symlink(src, dst),
+    };
+
+    if let Err(err) = linked {
+        debug_event!("failed to link the dylib, copying it: {}", err);
+        fs::copy(src, dst)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(src: &Utf8Path, dst: &Utf8Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(src, dst)
+}
+
+#[cfg(windows)]
+fn symlink(src: &Utf8Path, dst: &Utf8Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(src, dst)
+}
@@ -0,0 +1,200 @@
+//! Symbol wrappers
+
+use std::{ffi::c_void, fmt, marker::PhantomData, ops::Deref, sync::Arc};
+
+use libloading::Library;
+
+#[cfg(unix)]
+use libloading::os::unix::Symbol as RawSymbol;
+#[cfg(windows)]
+use libloading::os::windows::Symbol as RawSymbol;
+
+use crate::{HotCrate, Result};
+
+/// Symbol loaded with [`HotCrate::get_cached`]
+pub struct CachedSymbol<'lib, T> {
+    value: T,
+    _lib: PhantomData<&'lib HotCrate>,
+}
+
+impl<'lib, T> Deref for CachedSymbol<'lib, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<'lib, T> fmt::Debug for CachedSymbol<'lib, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedSymbol")
+            .field("size", &std::mem::size_of::<T>())
+            .finish()
+    }
+}
+
+/// Symbol that blocks reloading while it's alive. Created with [`HotCrate::get_guarded`].
+///
+/// Unlike [`Symbol`](crate::Symbol), it does not borrow the [`HotCrate`]. Instead,
+/// [`HotCrate::force_reload`] returns [`HotCrateError::Busy`](crate::HotCrateError::Busy) until
+/// all the guards are dropped, and the library is kept alive even if the [`HotCrate`] is dropped.
+pub struct SymbolGuard<T> {
+    // declared before `_lib` so that it's dropped first
+    symbol: RawSymbol<T>,
+    _lib: Arc<Library>,
+    _guard: Arc<()>,
+}
+
+impl<T> Deref for SymbolGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.symbol
+    }
+}
+
+impl<T> fmt::Debug for SymbolGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SymbolGuard")
+            .field("symbol", &self.symbol)
+            .finish()
+    }
+}
+
+/// Symbol that keeps its library alive across reloads. Created with [`HotCrate::get_shared`].
+///
+/// Unlike [`SymbolGuard`], it does not block reloading. The old library stays open until all the
+/// shared symbols from it are dropped, so function pointers into it remain valid.
+pub struct SharedSymbol<T> {
+    // declared before `_lib` so that it's dropped first
+    symbol: RawSymbol<T>,
+    _lib: Arc<Library>,
+}
+
+impl<T> Deref for SharedSymbol<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.symbol
+    }
+}
+
+impl<T> fmt::Debug for SharedSymbol<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSymbol")
+            .field("symbol", &self.symbol)
+            .finish()
+    }
+}
+
+impl HotCrate {
+    /// Returns true if the library exports the symbol. Useful for probing optional functions
+    /// before `unsafe` calls.
+    pub fn has_symbol(&self, symbol: &[u8]) -> bool {
+        // the symbol is only looked up, never called
+        unsafe { self.lib.get::<unsafe extern "C" fn()>(symbol).is_ok() }
+    }
+
+    #[deprecated(note = "renamed to `HotCrate::has_symbol`")]
+    pub fn symbol_exists(&self, symbol: &[u8]) -> bool {
+        self.has_symbol(symbol)
+    }
+
+    /// Loads a symbol and passes it to the closure, so that the symbol can't outlive the call
+    ///
+    /// # Safety
+    ///
+    /// `T` must match the actual type of the symbol, and the closure must not leak values that
+    /// borrow from the library (e.g. `&'static str`s returned by the symbol).
+    pub unsafe fn with_symbol<T, R>(&self, symbol: &[u8], f: impl FnOnce(&T) -> R) -> Result<R> {
+        let symbol = self.lib.get::<T>(symbol)?;
+        Ok(f(&symbol))
+    }
+
+    /// Copies the value of a data symbol (`static` item) out of the library
+    ///
+    /// Unlike a `&T` into the library, the copy stays valid after reloading. This is `unsafe`
+    /// because nothing checks the type of the symbol; a wrong `T` reads arbitrary memory.
+    ///
+    /// # Safety
+    ///
+    /// The symbol must be a `static` of type `T`, and `T` must not borrow from the library (e.g.
+    /// `&'static str`).
+    pub unsafe fn with_symbol_typed<T: Copy>(&self, symbol: &[u8]) -> Result<T> {
+        let ptr = self.lib.get::<*const T>(symbol)?;
+        Ok(std::ptr::read(*ptr))
+    }
+
+    /// [`HotCrate::get`] returning a [`SymbolGuard`]
+    ///
+    /// # Safety
+    ///
+    /// `T` must match the actual type of the symbol.
+    pub unsafe fn get_guarded<T>(&self, symbol: &[u8]) -> Result<SymbolGuard<T>> {
+        let symbol = self.lib.get::<T>(symbol)?.into_raw();
+
+        Ok(SymbolGuard {
+            symbol,
+            _lib: Arc::clone(&self.lib),
+            _guard: Arc::clone(&self.guards),
+        })
+    }
+
+    /// [`HotCrate::get`] returning a [`SharedSymbol`]
+    ///
+    /// # Safety
+    ///
+    /// `T` must match the actual type of the symbol.
+    pub unsafe fn get_shared<T>(&self, symbol: &[u8]) -> Result<SharedSymbol<T>> {
+        let symbol = self.lib.get::<T>(symbol)?.into_raw();
+
+        Ok(SharedSymbol {
+            symbol,
+            _lib: Arc::clone(&self.lib),
+        })
+    }
+
+    /// [`HotCrate::get`] backed by a cache, which is cleared on reload
+    ///
+    /// # Safety
+    ///
+    /// `T` must match the actual type of the symbol.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is not pointer-sized.
+    pub unsafe fn get_cached<'lib, T>(&'lib self, symbol: &[u8]) -> Result<CachedSymbol<'lib, T>> {
+        assert_eq!(
+            std::mem::size_of::<T>(),
+            std::mem::size_of::<*mut c_void>(),
+            "cached symbols must be pointer-sized"
+        );
+
+        let mut cache = self
+            .symbol_cache
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        let ptr = match cache.get(symbol) {
+            Some(ptr) => ptr.0,
+            None => {
+                let ptr = *self.lib.get::<*mut c_void>(symbol)?;
+                cache.insert(symbol.to_vec(), SymbolAddress(ptr));
+                ptr
+            }
+        };
+
+        Ok(CachedSymbol {
+            value: std::mem::transmute_copy(&ptr),
+            _lib: PhantomData,
+        })
+    }
+}
+
+/// Address of a symbol cached by [`HotCrate::get_cached`]
+///
+/// Raw pointers are not `Send` or `Sync`, but the address is only a value here: it's never
+/// dereferenced by `hot_crate`, and it's turned back into a symbol only by the unsafe getter while
+/// the library is loaded. The cache is cleared on reload.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SymbolAddress(pub(crate) *mut c_void);
+
+unsafe impl Send for SymbolAddress {}
+unsafe impl Sync for SymbolAddress {}
@@ -0,0 +1,84 @@
+//! Checking the ABI version of the dylib on every load
+
+use std::ops::{Deref, DerefMut};
+
+use libloading::Library;
+
+use crate::{HotCrate, HotCrateError, Result, Utf8Path};
+
+/// [`HotCrate`] that refuses dylibs exporting an unexpected `hot_crate_api_version`
+///
+/// The dylib exports the version like this:
+///
+/// ```ignore
+/// #[no_mangle]
+/// pub extern "C" fn hot_crate_api_version() -> u32 {
+///     3
+/// }
+/// ```
+///
+/// [`HotCrate::force_reload`] (and so [`HotCrate::try_reload`]) fails with
+/// [`HotCrateError::AbiVersionMismatch`] if the rebuilt dylib exports another version, keeping
+/// the current library loaded instead of crashing later on an incompatible one.
+#[derive(Debug)]
+pub struct VersionedHotCrate {
+    hot: HotCrate,
+    expected: u32,
+}
+
+impl VersionedHotCrate {
+    /// [`HotCrate::load`] expecting the API version
+    pub fn load(
+        main_toml: impl AsRef<Utf8Path>,
+        dylib_toml: impl AsRef<Utf8Path>,
+        expected: u32,
+    ) -> Result<Self> {
+        Self::new(HotCrate::load(main_toml, dylib_toml)?, expected)
+    }
+
+    /// Checks the API version of the loaded library and of every library loaded afterwards
+    pub fn new(mut hot: HotCrate, expected: u32) -> Result<Self> {
+        self::check_version(&hot.lib, expected)?;
+        hot.hooks.validators.push(std::sync::Arc::new(move |lib| {
+            self::check_version(lib, expected)
+        }));
+
+        Ok(Self { hot, expected })
+    }
+
+    pub fn expected_version(&self) -> u32 {
+        self.expected
+    }
+
+    /// Returns the [`HotCrate`], which keeps checking the version on reload
+    pub fn into_inner(self) -> HotCrate {
+        self.hot
+    }
+}
+
+impl Deref for VersionedHotCrate {
+    type Target = HotCrate;
+
+    fn deref(&self) -> &HotCrate {
+        &self.hot
+    }
+}
+
+impl DerefMut for VersionedHotCrate {
+    fn deref_mut(&mut self) -> &mut HotCrate {
+        &mut self.hot
+    }
+}
+
+fn check_version(lib: &Library, expected: u32) -> Result<()> {
+    let found = unsafe {
+        let version = lib.get::<unsafe extern "C" fn() -> u32>(b"hot_crate_api_version")?;
+        version()
+    };
+
+    if found == expected {
+        Ok(())
+    } else {
+        Err(HotCrateError::AbiVersionMismatch { expected, found })
+    }
+}
@@ -0,0 +1,105 @@
+//! Hot-swapping WASM modules with `wasmtime`
+
+use std::{fs, time::SystemTime};
+
+use wasmtime::{Engine, Instance, Module, Store, Val};
+
+use crate::{HotCrateError, Result, Utf8Path, Utf8PathBuf};
+
+/// [`HotCrate`](crate::HotCrate) for sandboxed WASM plugins, such as those built for
+/// `wasm32-unknown-unknown`
+///
+/// The module is instantiated without imports. The instance (and its memory) is recreated on
+/// reload, so no state survives reloading.
+pub struct WasmHotCrate {
+    engine: Engine,
+    store: Store<()>,
+    instance: Instance,
+    module_path: Utf8PathBuf,
+    modified: Option<SystemTime>,
+    reload_count: usize,
+}
+
+impl std::fmt::Debug for WasmHotCrate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmHotCrate")
+            .field("module_path", &self.module_path)
+            .field("modified", &self.modified)
+            .field("reload_count", &self.reload_count)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WasmHotCrate {
+    /// Compiles and instantiates the `.wasm` file
+    pub fn load(module_path: impl AsRef<Utf8Path>) -> Result<Self> {
+        let module_path = module_path.as_ref().to_path_buf();
+        let engine = Engine::default();
+        let modified = fs::metadata(&module_path)?.modified().ok();
+        let (store, instance) = self::instantiate(&engine, &module_path)?;
+
+        Ok(Self {
+            engine,
+            store,
+            instance,
+            module_path,
+            modified,
+            reload_count: 0,
+        })
+    }
+
+    pub fn module_path(&self) -> &Utf8Path {
+        &self.module_path
+    }
+
+    pub fn reload_count(&self) -> usize {
+        self.reload_count
+    }
+
+    /// Reloads the module if the `.wasm` file is modified. Returns true if succeed in reloading.
+    pub fn try_reload(&mut self) -> Result<bool> {
+        let modified = fs::metadata(&self.module_path)?.modified().ok();
+        if modified.is_none() || modified == self.modified {
+            return Ok(false);
+        }
+
+        self.force_reload()?;
+        Ok(true)
+    }
+
+    /// Reloads the module. The current instance is kept if the new module fails to compile or
+    /// instantiate.
+    pub fn force_reload(&mut self) -> Result<()> {
+        let modified = fs::metadata(&self.module_path)?.modified().ok();
+        let (store, instance) = self::instantiate(&self.engine, &self.module_path)?;
+
+        self.store = store;
+        self.instance = instance;
+        self.modified = modified;
+        self.reload_count += 1;
+
+        debug_event!("reloaded WASM module `{}`", self.module_path);
+        Ok(())
+    }
+
+    /// Calls an exported function. Fails with [`HotCrateError::SymbolNotFound`] if the module
+    /// does not export a function named `name`.
+    pub fn call_export(&mut self, name: &str, args: &[Val]) -> Result<Vec<Val>> {
+        let func = self
+            .instance
+            .get_func(&mut self.store, name)
+            .ok_or_else(|| HotCrateError::SymbolNotFound(name.to_string()))?;
+
+        let mut results = vec![Val::I32(0); func.ty(&self.store).results().len()];
+        func.call(&mut self.store, args, &mut results)?;
+
+        Ok(results)
+    }
+}
+
+fn instantiate(engine: &Engine, module_path: &Utf8Path) -> Result<(Store<()>, Instance)> {
+    let module = Module::from_file(engine, module_path)?;
+    let mut store = Store::new(engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    Ok((store, instance))
+}
@@ -0,0 +1,216 @@
+//! Filesystem watcher of the dylib (`watch` feature)
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use notify::{RecursiveMode, Watcher as _};
+
+use crate::{error::ErrorChain, HotCrate, HotCrateError, Result, Utf8Path};
+
+/// Stops watching the dylib on drop. Created with [`HotCrate::watch`].
+#[derive(Debug)]
+pub struct WatchHandle {
+    watcher: Option<notify::RecommendedWatcher>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        // dropping the watcher closes the channel and the thread exits
+        drop(self.watcher.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Change of the build artifact, sent by [`Watcher`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReloadEvent {
+    pub package_name: String,
+    /// Sequence number of the event, starting from 1. One `cargo build` can send multiple
+    /// events, and the dylib is not reloaded by the [`Watcher`], so this is not
+    /// [`HotCrate::reload_count`].
+    pub sequence: usize,
+}
+
+/// Sends [`ReloadEvent`]s until dropped. Created with [`HotCrate::watcher`].
+#[derive(Debug)]
+pub struct Watcher {
+    _handle: WatchHandle,
+}
+
+impl HotCrate {
+    /// Calls `callback` from a background thread whenever the build artifact changes
+    ///
+    /// The dylib is not reloaded automatically; call [`HotCrate::try_reload`] afterwards. The
+    /// callback can be called multiple times for one `cargo build`.
+    pub fn watch(&self, callback: impl Fn() + Send + 'static) -> Result<WatchHandle> {
+        let (watcher, rx) = self::watch_file(&self.lib_path)?;
+
+        let thread = std::thread::spawn(move || {
+            for () in rx {
+                callback();
+            }
+        });
+
+        Ok(WatchHandle {
+            watcher: Some(watcher),
+            thread: Some(thread),
+        })
+    }
+
+    /// [`HotCrate::watch`] sending [`ReloadEvent`]s to a channel, which can be used with
+    /// `crossbeam_channel::select!`
+    ///
+    /// The channel is disconnected when the [`Watcher`] is dropped.
+    pub fn watcher(&self) -> Result<(Watcher, crossbeam_channel::Receiver<ReloadEvent>)> {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let package_name = self.package_name.clone();
+        let count = AtomicUsize::new(0);
+
+        let handle = self.watch(move || {
+            let sequence = count.fetch_add(1, Ordering::Relaxed) + 1;
+            let _ = tx.send(ReloadEvent {
+                package_name: package_name.clone(),
+                sequence,
+            });
+        })?;
+
+        Ok((Watcher { _handle: handle }, rx))
+    }
+}
+
+/// Stops watching the sources on drop or [`WatchAndRebuildHandle::stop`]. Created with
+/// [`HotCrate::watch_and_rebuild`].
+#[derive(Debug)]
+pub struct WatchAndRebuildHandle {
+    _handle: WatchHandle,
+}
+
+impl WatchAndRebuildHandle {
+    /// Stops watching and waits for the running build (if any) to finish
+    pub fn stop(self) {}
+}
+
+impl HotCrate {
+    /// Runs `cargo build` and [`HotCrate::force_reload`] from a background thread whenever a `.rs`
+    /// file under the `src/` directory of the dylib package changes, like `cargo watch`
+    ///
+    /// The [`HotCrate`] is locked while building and reloading. Build failures are logged and the
+    /// current library is kept. Fails with [`HotCrateError::NoMetadata`] if the dylib is loaded
+    /// with [`HotCrate::load_path`].
+    pub fn watch_and_rebuild(hot: Arc<Mutex<Self>>) -> Result<WatchAndRebuildHandle> {
+        let src_dir = {
+            let hot = hot.lock().unwrap_or_else(|err| err.into_inner());
+            let main_metadata = hot.metadata().ok_or(HotCrateError::NoMetadata)?;
+            let dylib_toml = hot.dylib_toml().ok_or(HotCrateError::NoMetadata)?;
+            let pkg = crate::find_dylib_pkg(main_metadata, dylib_toml)?;
+            pkg.manifest_path.parent().unwrap().join("src")
+        };
+
+        let (watcher, rx) = self::watch_sources(&src_dir)?;
+
+        let thread = std::thread::spawn(move || {
+            while rx.recv().is_ok() {
+                // one save can emit multiple events
+                while rx.try_recv().is_ok() {}
+
+                // a user thread panicking with the lock shouldn't stop the watcher
+                let mut hot = hot.lock().unwrap_or_else(|err| err.into_inner());
+
+                if let Err(err) = self::rebuild_and_force_reload(&mut hot) {
+                    log::warn!(
+                        "failed to rebuild `{}`: {}",
+                        hot.package_name(),
+                        ErrorChain(&err)
+                    );
+                }
+            }
+        });
+
+        Ok(WatchAndRebuildHandle {
+            _handle: WatchHandle {
+                watcher: Some(watcher),
+                thread: Some(thread),
+            },
+        })
+    }
+}
+
+fn rebuild_and_force_reload(hot: &mut HotCrate) -> Result<()> {
+    let output = hot.cargo_build_command(&hot.build_options)?.output()?;
+    crate::rebuild::check_build_output(&output)?;
+    hot.force_reload()
+}
+
+/// Watches `.rs` files under `dir` recursively
+fn watch_sources(dir: &Utf8Path) -> Result<(notify::RecommendedWatcher, mpsc::Receiver<()>)> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => {
+                log::warn!("hot_crate watcher error: {}", err);
+                return;
+            }
+        };
+
+        if !(event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove()) {
+            return;
+        }
+
+        if event
+            .paths
+            .iter()
+            .any(|p| p.extension().and_then(|s| s.to_str()) == Some("rs"))
+        {
+            let _ = tx.send(());
+        }
+    })?;
+
+    watcher.watch(dir.as_std_path(), RecursiveMode::Recursive)?;
+
+    Ok((watcher, rx))
+}
+
+/// Watches the parent directory of `file`, because the linker replaces the file rather than
+/// modifying it
+pub(crate) fn watch_file(
+    file: &Utf8Path,
+) -> Result<(notify::RecommendedWatcher, mpsc::Receiver<()>)> {
+    let dir = file.parent().unwrap();
+    let file_name = file.file_name().map(|s| s.to_string());
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => {
+                log::warn!("hot_crate watcher error: {}", err);
+                return;
+            }
+        };
+
+        if !(event.kind.is_create() || event.kind.is_modify()) {
+            return;
+        }
+
+        if event
+            .paths
+            .iter()
+            .any(|p| p.file_name().and_then(|s| s.to_str()) == file_name.as_deref())
+        {
+            let _ = tx.send(());
+        }
+    })?;
+
+    watcher.watch(dir.as_std_path(), RecursiveMode::NonRecursive)?;
+
+    Ok((watcher, rx))
+}
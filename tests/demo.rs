@@ -0,0 +1,26 @@
+//! Loads the demo plugin, which checks the dylib file name convention of each platform
+
+use std::process::Command;
+
+use hot_crate::{HotCrate, Utf8PathBuf};
+
+#[test]
+fn load_demo_plugin() {
+    let demo = Utf8PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("demo");
+
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut build = Command::new(cargo);
+    build
+        .arg("build")
+        .arg("--manifest-path")
+        .arg(demo.join("Cargo.toml"))
+        .arg("-p")
+        .arg("plugins");
+    if !cfg!(debug_assertions) {
+        build.arg("--release");
+    }
+    assert!(build.status().unwrap().success());
+
+    let plugin = HotCrate::load(demo.join("Cargo.toml"), demo.join("plugins/Cargo.toml")).unwrap();
+    assert!(unsafe { plugin.get::<extern "C" fn()>(b"load_plugin") }.is_ok());
+}
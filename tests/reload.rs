@@ -0,0 +1,192 @@
+//! Builds a toy dylib, rewrites its source and reloads it. Enabled with the `integration-tests`
+//! feature.
+
+#![cfg(feature = "integration-tests")]
+
+use std::{
+    fs,
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use hot_crate::{HotCrate, Utf8PathBuf};
+
+fn write_lib(dir: &Utf8PathBuf, value: i32) {
+    let src = format!(
+        "#[no_mangle]\npub extern \"C\" fn value() -> i32 {{\n    {}\n}}\n",
+        value
+    );
+    fs::write(dir.join("src/lib.rs"), src).unwrap();
+}
+
+fn cargo_build(manifest: &Utf8PathBuf) {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut build = Command::new(cargo);
+    build.arg("build").arg("--manifest-path").arg(manifest);
+    if !cfg!(debug_assertions) {
+        build.arg("--release");
+    }
+    assert!(build.status().unwrap().success());
+}
+
+/// Creates and builds a cdylib package returning `value`. Returns the package directory and the
+/// manifest path.
+fn toy_package(name: &str, value: i32) -> (Utf8PathBuf, Utf8PathBuf) {
+    let dir = Utf8PathBuf::from_path_buf(std::env::temp_dir())
+        .unwrap()
+        .join(format!("hot_crate_{}_{}", name, std::process::id()));
+    fs::create_dir_all(dir.join("src")).unwrap();
+
+    let manifest = dir.join("Cargo.toml");
+    fs::write(
+        &manifest,
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.0.0\"\nedition = \"2018\"\n\n\
+             [lib]\ncrate-type = [\"cdylib\"]\n\n[workspace]\n",
+            name
+        ),
+    )
+    .unwrap();
+    write_lib(&dir, value);
+    cargo_build(&manifest);
+
+    (dir, manifest)
+}
+
+fn hash(hot: &HotCrate) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    hot.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn value(hot: &HotCrate) -> i32 {
+    unsafe { hot.call0::<i32>(b"value") }.unwrap()
+}
+
+#[test]
+fn rebuild_and_reload_toy_dylib() {
+    let (dir, manifest) = toy_package("toy", 1);
+
+    let mut toy = HotCrate::load(&manifest, &manifest).unwrap();
+    assert_eq!(value(&toy), 1);
+
+    write_lib(&dir, 2);
+    assert!(toy.try_rebuild_and_reload().unwrap());
+    assert_eq!(value(&toy), 2);
+    assert_eq!(toy.reload_count(), 1);
+
+    drop(toy);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn batch_rolls_back_when_a_later_reload_fails() {
+    let (dir_a, manifest_a) = toy_package("toy_batch_a", 1);
+    let (dir_b, manifest_b) = toy_package("toy_batch_b", 10);
+
+    let mut a = HotCrate::load(&manifest_a, &manifest_a).unwrap();
+    let b = HotCrate::load(&manifest_b, &manifest_b).unwrap();
+
+    let before = Arc::new(AtomicUsize::new(0));
+    let after = Arc::new(AtomicUsize::new(0));
+    {
+        let before = Arc::clone(&before);
+        a.on_before_reload(move || {
+            before.fetch_add(1, Ordering::SeqCst);
+        });
+        let after = Arc::clone(&after);
+        a.on_after_reload(move || {
+            after.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    write_lib(&dir_a, 2);
+    cargo_build(&manifest_a);
+    // `b` is outdated but can't be opened
+    fs::write(b.build_artifact_path(), b"not a library").unwrap();
+
+    let mut crates = vec![a, b];
+    assert!(HotCrate::try_reload_batch(&mut crates).is_err());
+
+    let a = &crates[0];
+    assert_eq!(value(a), 1);
+    assert_eq!(a.reload_count(), 0);
+    assert_eq!(a.reload_stats().len(), 0);
+    assert_eq!(a.total_reload_duration(), std::time::Duration::ZERO);
+    assert_eq!(a.avg_reload_duration(), None);
+    // the reload and the rollback
+    assert_eq!(before.load(Ordering::SeqCst), 2);
+    assert_eq!(after.load(Ordering::SeqCst), 2);
+    assert!(a.is_stale().unwrap());
+
+    drop(crates);
+    let _ = fs::remove_dir_all(&dir_a);
+    let _ = fs::remove_dir_all(&dir_b);
+}
+
+#[test]
+fn same_package_through_another_path_is_equal() {
+    let (dir, manifest) = toy_package("toy_identity", 1);
+    let dotted = dir
+        .join("..")
+        .join(dir.file_name().unwrap())
+        .join("Cargo.toml");
+
+    let a = HotCrate::load(&manifest, &manifest).unwrap();
+    let b = HotCrate::load(&dotted, &dotted).unwrap();
+    assert_eq!(a, b);
+    assert_eq!(hash(&a), hash(&b));
+
+    drop((a, b));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn restore_snapshot_after_reload() {
+    let (dir, manifest) = toy_package("toy_snapshot", 1);
+
+    let mut toy = HotCrate::load(&manifest, &manifest).unwrap();
+    let snap = toy.snapshot();
+
+    write_lib(&dir, 2);
+    assert!(toy.try_rebuild_and_reload().unwrap());
+    assert_eq!(value(&toy), 2);
+
+    toy.restore(snap).unwrap();
+    assert_eq!(value(&toy), 1);
+    assert_eq!(toy.reload_count(), 0);
+    // v2 is still the build artifact, considered loaded
+    assert!(!toy.is_stale().unwrap());
+
+    drop(toy);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn in_place_reload_falls_back_to_copy_while_open() {
+    let (dir, manifest) = toy_package("toy_in_place", 1);
+
+    let mut toy = HotCrate::load(&manifest, &manifest).unwrap();
+
+    write_lib(&dir, 2);
+    cargo_build(&manifest);
+    toy.force_reload_in_place().unwrap();
+    assert_eq!(value(&toy), 2);
+    assert_eq!(toy.loaded_path(), toy.build_artifact_path());
+
+    // the build artifact is open, so `dlopen` would return the stale v2
+    write_lib(&dir, 3);
+    cargo_build(&manifest);
+    toy.force_reload_in_place().unwrap();
+    assert_eq!(value(&toy), 3);
+    assert_ne!(toy.loaded_path(), toy.build_artifact_path());
+
+    drop(toy);
+    let _ = fs::remove_dir_all(&dir);
+}